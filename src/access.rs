@@ -0,0 +1,295 @@
+use author::AuthorId;
+use remnant::{Content, NodeId, Remnant};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use triefort;
+
+/// Who may add to a timeline. An Origin declares it, and a Membership
+/// node replaces it for everything that follows.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// Anyone may append or join. This is what origins without a
+    /// policy get.
+    #[default]
+    Open,
+
+    /// Only the author of the origin.
+    Owner,
+
+    /// The author of the origin and these authors.
+    Members(Vec<AuthorId>),
+}
+
+impl Access {
+    /// Whether `author` may add to a timeline owned by `owner`.
+    pub fn allows(&self, owner: &AuthorId, author: &AuthorId) -> bool {
+        match self {
+            Access::Open => true,
+            Access::Owner => author == owner,
+            Access::Members(m) => author == owner || m.contains(author),
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Open => write!(f, "open"),
+            Access::Owner => write!(f, "owner only"),
+            Access::Members(m) => {
+                write!(f, "members")?;
+                for (i, a) in m.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, a)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The access in effect for one timeline, along with the author of its
+/// origin. Only the owner may change the access.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Rule {
+    pub owner: AuthorId,
+    pub access: Access,
+}
+
+impl Rule {
+    /// Whether `r` may follow a node this rule is in effect at.
+    /// Membership changes are for the owner alone.
+    pub fn allows(&self, r: &Remnant) -> bool {
+        match r.content() {
+            Content::Membership { .. } => *r.author() == self.owner,
+            _ => self.access.allows(&self.owner, r.author()),
+        }
+    }
+}
+
+impl triefort::Handle<Remnant> {
+    /// The rules a node following `id` has to satisfy. There is one
+    /// for each timeline `id` descends from, since joins can bring in
+    /// history from timelines with other owners.
+    pub fn rules(&self, id: &NodeId) -> io::Result<Vec<Rule>> {
+        rules(self, &HashMap::new(), &mut HashMap::new(), id)
+    }
+
+    /// Check that the author of `r` is allowed to add it after its
+    /// parents.
+    pub fn permits(&self, r: &Remnant) -> io::Result<()> {
+        check(self, std::slice::from_ref(r))
+    }
+}
+
+/// Check every one of `remnants` against the rules in effect at its
+/// parents. Parents are looked up in `remnants` before `db`, so a
+/// bundle can be checked before it's imported.
+pub fn check(db: &triefort::Handle<Remnant>, remnants: &[Remnant]) -> io::Result<()> {
+    let local: HashMap<&NodeId, &Remnant> = remnants.iter().map(|r| (r.id(), r)).collect();
+    let mut memo = HashMap::new();
+
+    for r in remnants {
+        for p in r.content().parents() {
+            let denied = rules(db, &local, &mut memo, p)?
+                .into_iter()
+                .find(|rule| !rule.allows(r));
+
+            if let Some(rule) = denied {
+                let msg = match r.content() {
+                    Content::Membership { .. } => format!(
+                        "only {} may change who can add after {}, not {}",
+                        rule.owner,
+                        p,
+                        r.author()
+                    ),
+                    _ => format!(
+                        "{} may not add to {}, which is {}",
+                        r.author(),
+                        p,
+                        rule.access
+                    ),
+                };
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, msg));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The parts of a node that decide the rules after it.
+struct Step {
+    author: AuthorId,
+    parents: Vec<NodeId>,
+    sets: Option<Access>,
+}
+
+fn step(
+    db: &triefort::Handle<Remnant>,
+    local: &HashMap<&NodeId, &Remnant>,
+    id: &NodeId,
+) -> io::Result<Step> {
+    let owned;
+    let r = match local.get(id) {
+        Some(r) => *r,
+        None => {
+            owned = db.get(id.bytes())?;
+            &owned
+        }
+    };
+
+    let sets = match r.content() {
+        Content::Origin { access: a, .. } | Content::Membership { access: a, .. } => {
+            Some(a.clone())
+        }
+        _ => None,
+    };
+
+    Ok(Step {
+        author: r.author().clone(),
+        parents: r.content().parents().into_iter().cloned().collect(),
+        sets,
+    })
+}
+
+/// Work out the rules after `id`, parents first. `memo` keeps the
+/// rules already found so shared history is only walked once.
+fn rules(
+    db: &triefort::Handle<Remnant>,
+    local: &HashMap<&NodeId, &Remnant>,
+    memo: &mut HashMap<NodeId, Vec<Rule>>,
+    id: &NodeId,
+) -> io::Result<Vec<Rule>> {
+    let mut todo = vec![id.clone()];
+
+    while let Some(i) = todo.last().cloned() {
+        if memo.contains_key(&i) {
+            todo.pop();
+            continue;
+        }
+
+        let s = step(db, local, &i)?;
+        let waiting: Vec<NodeId> = s
+            .parents
+            .iter()
+            .filter(|p| !memo.contains_key(*p))
+            .cloned()
+            .collect();
+        if !waiting.is_empty() {
+            todo.extend(waiting);
+            continue;
+        }
+        todo.pop();
+
+        let mut found: Vec<Rule> = Vec::new();
+        for p in &s.parents {
+            for rule in &memo[p] {
+                if !found.contains(rule) {
+                    found.push(rule.clone());
+                }
+            }
+        }
+
+        let found = match s.sets {
+            // An origin's author owns the timeline it starts.
+            Some(access) if s.parents.is_empty() => vec![Rule {
+                owner: s.author,
+                access,
+            }],
+            // A membership change keeps the owners it follows, so a
+            // change nobody was allowed to make can't take over.
+            Some(access) => found
+                .into_iter()
+                .map(|rule| Rule {
+                    owner: rule.owner,
+                    access: access.clone(),
+                })
+                .fold(Vec::new(), |mut v, rule| {
+                    if !v.contains(&rule) {
+                        v.push(rule);
+                    }
+                    v
+                }),
+            None => found,
+        };
+
+        memo.insert(i, found);
+    }
+
+    Ok(memo[id].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+    use remnant::OriginOptions;
+    use tempdir;
+
+    #[test]
+    fn only_members_may_append() {
+        let tdir = tempdir::TempDir::new("access_test").unwrap();
+        let db = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let owner = Author::new();
+        let member = Author::new();
+        let outsider = Author::new();
+
+        let opts = OriginOptions {
+            access: Access::Members(vec![member.id().clone()]),
+            ..OriginOptions::default()
+        };
+        let o = Remnant::origin_with(&owner, "group", opts);
+        db.insert(&o).unwrap();
+
+        let m = o.append(&member, b"hello");
+        db.permits(&m).unwrap();
+        db.insert(&m).unwrap();
+        assert!(db.permits(&m.append(&outsider, b"let me in")).is_err());
+
+        // Members can't change who the members are.
+        let grab = m.membership(&member, Access::Open);
+        assert!(db.permits(&grab).is_err());
+
+        let closed = m.membership(&owner, Access::Owner);
+        db.permits(&closed).unwrap();
+        db.insert(&closed).unwrap();
+        assert!(db.permits(&closed.append(&member, b"still here?")).is_err());
+        db.permits(&closed.append(&owner, b"just me")).unwrap();
+
+        // The old membership still holds on the branch before the
+        // change.
+        db.permits(&m.append(&member, b"a side branch")).unwrap();
+
+        // A join needs to be allowed after both sides.
+        let side = m.append(&member, b"side");
+        db.insert(&side).unwrap();
+        assert!(db.permits(&Remnant::join(&member, &closed, &side)).is_err());
+        db.permits(&Remnant::join(&owner, &closed, &side)).unwrap();
+    }
+
+    #[test]
+    fn bundles_are_checked_before_they_are_stored() {
+        let tdir = tempdir::TempDir::new("access_test").unwrap();
+        let db = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let owner = Author::new();
+        let outsider = Author::new();
+
+        let opts = OriginOptions {
+            access: Access::Owner,
+            ..OriginOptions::default()
+        };
+        let o = Remnant::origin_with(&owner, "mine", opts);
+        let a = o.append(&owner, b"first");
+        let b = a.append(&outsider, b"sneaky");
+        let open = Remnant::origin(&owner, "anyone");
+        let c = open.append(&outsider, b"welcome");
+
+        check(&db, &[o.clone(), a.clone(), open, c]).unwrap();
+        assert!(check(&db, &[o, a, b]).is_err());
+        assert!(db.keys().unwrap().is_empty());
+    }
+}
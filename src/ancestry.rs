@@ -0,0 +1,286 @@
+use remnant::{NodeId, Remnant};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use triefort;
+
+impl triefort::Handle<Remnant> {
+    /// The identifiers of the nodes that `id` directly follows.
+    pub fn parents(&self, id: &NodeId) -> io::Result<Vec<NodeId>> {
+        let r = self.get(id.bytes())?;
+        Ok(r.content().parents().into_iter().cloned().collect())
+    }
+
+    /// Every node reachable from `id` by following `Append` and
+    /// `Join` edges, in breadth-first order. `id` itself is the first
+    /// element.
+    pub fn ancestors(&self, id: &NodeId) -> io::Result<Vec<NodeId>> {
+        self.walk(id, |_| false)
+    }
+
+    /// Check whether `ancestor` is reachable from `descendant`. A
+    /// node is considered its own ancestor.
+    pub fn is_ancestor(&self, ancestor: &NodeId, descendant: &NodeId) -> io::Result<bool> {
+        let mut found = false;
+        self.walk(descendant, |i| {
+            found = found || i == ancestor;
+            found
+        })?;
+        Ok(found)
+    }
+
+    /// The best common ancestors of `left` and `right`. A common
+    /// ancestor is best if it is not an ancestor of any other common
+    /// ancestor. Joins can produce more than one; they are returned
+    /// sorted by identifier. Nodes from unrelated timelines have none.
+    pub fn merge_bases(&self, left: &NodeId, right: &NodeId) -> io::Result<Vec<NodeId>> {
+        let from_left: HashSet<NodeId> = self.ancestors(left)?.into_iter().collect();
+        let common: Vec<NodeId> = self
+            .ancestors(right)?
+            .into_iter()
+            .filter(|i| from_left.contains(i))
+            .collect();
+
+        // Common ancestors are closed under parents, so anything that
+        // is a proper ancestor of a common ancestor is also the direct
+        // parent of one.
+        let mut shadowed = HashSet::new();
+        for i in &common {
+            shadowed.extend(self.parents(i)?);
+        }
+
+        let mut bases: Vec<NodeId> = common
+            .into_iter()
+            .filter(|i| !shadowed.contains(i))
+            .collect();
+        bases.sort_by(|a, b| a.bytes().cmp(b.bytes()));
+
+        Ok(bases)
+    }
+
+    /// The nodes reachable from `to` that are not reachable from
+    /// `from`, in breadth-first order starting at `to`. This mirrors
+    /// `git log from..to`.
+    pub fn range(&self, from: &NodeId, to: &NodeId) -> io::Result<Vec<NodeId>> {
+        let excluded: HashSet<NodeId> = self.ancestors(from)?.into_iter().collect();
+        self.walk_excluding(to, &excluded, |_| false)
+    }
+
+    /// Every node that has `id` as an ancestor, in breadth-first
+    /// order starting with `id` itself. Nodes only record their
+    /// parents, so this reads the whole database.
+    pub fn descendants(&self, id: &NodeId) -> io::Result<Vec<NodeId>> {
+        let mut children: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+
+        for k in self.keys()? {
+            let r = self.get(&k)?;
+            for p in r.content().parents() {
+                children.entry(p.clone()).or_default().push(r.id().clone());
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::new();
+        let mut visited = Vec::new();
+
+        queue.push_back(id.clone());
+
+        while let Some(i) = queue.pop_front() {
+            if !seen.insert(i.clone()) {
+                continue;
+            }
+
+            if let Some(cs) = children.get(&i) {
+                queue.extend(cs.iter().cloned());
+            }
+            visited.push(i);
+        }
+
+        Ok(visited)
+    }
+
+    /// Order `nodes` so that every node comes before its parents.
+    /// Each line of history is kept together where possible, and
+    /// parents outside of `nodes` are ignored.
+    pub fn topological(&self, nodes: &[NodeId]) -> io::Result<Vec<NodeId>> {
+        let members: HashSet<&NodeId> = nodes.iter().collect();
+        let mut parents: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        let mut pending: HashMap<NodeId, usize> = HashMap::new();
+
+        for i in nodes {
+            let ps: Vec<NodeId> = self
+                .parents(i)?
+                .into_iter()
+                .filter(|p| members.contains(p))
+                .collect();
+            for p in &ps {
+                *pending.entry(p.clone()).or_insert(0) += 1;
+            }
+            parents.insert(i.clone(), ps);
+        }
+
+        // Start from the heads. The stack is popped from the end, so
+        // push in reverse to visit them in identifier order.
+        let mut stack: Vec<NodeId> = nodes
+            .iter()
+            .filter(|i| !pending.contains_key(i))
+            .cloned()
+            .collect();
+        stack.sort_by(|a, b| b.bytes().cmp(a.bytes()));
+        stack.dedup();
+
+        let mut order = Vec::with_capacity(nodes.len());
+
+        while let Some(i) = stack.pop() {
+            for p in parents[&i].iter().rev() {
+                let n = pending.get_mut(p).unwrap();
+                *n -= 1;
+                if *n == 0 {
+                    stack.push(p.clone());
+                }
+            }
+            order.push(i);
+        }
+
+        Ok(order)
+    }
+
+    /// Breadth-first walk from `start`, stopping early once `stop`
+    /// returns true. Returns the nodes visited.
+    fn walk<F>(&self, start: &NodeId, stop: F) -> io::Result<Vec<NodeId>>
+    where
+        F: FnMut(&NodeId) -> bool,
+    {
+        self.walk_excluding(start, &HashSet::new(), stop)
+    }
+
+    fn walk_excluding<F>(
+        &self,
+        start: &NodeId,
+        excluded: &HashSet<NodeId>,
+        mut stop: F,
+    ) -> io::Result<Vec<NodeId>>
+    where
+        F: FnMut(&NodeId) -> bool,
+    {
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::new();
+        let mut visited = Vec::new();
+
+        queue.push_back(start.clone());
+
+        while let Some(i) = queue.pop_front() {
+            if excluded.contains(&i) || !seen.insert(i.clone()) {
+                continue;
+            }
+
+            visited.push(i.clone());
+            if stop(&i) {
+                break;
+            }
+
+            queue.extend(self.parents(&i)?);
+        }
+
+        Ok(visited)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use author::Author;
+    use remnant::{NodeId, Remnant};
+    use tempdir;
+    use triefort;
+
+    fn open(tdir: &tempdir::TempDir) -> triefort::Handle<Remnant> {
+        triefort::open(tdir.path().to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn merge_base_of_a_fork() {
+        let tdir = tempdir::TempDir::new("ancestry_test").unwrap();
+        let hdl = open(&tdir);
+        let a = Author::new();
+
+        // o - x - l1 - l2
+        //       \
+        //        r1
+        let o = Remnant::origin(&a, "fork");
+        let x = o.append(&a, b"x");
+        let l1 = x.append(&a, b"l1");
+        let l2 = l1.append(&a, b"l2");
+        let r1 = x.append(&a, b"r1");
+
+        for r in &[&o, &x, &l1, &l2, &r1] {
+            hdl.insert(r).unwrap();
+        }
+
+        assert_eq!(
+            vec![x.id().clone()],
+            hdl.merge_bases(l2.id(), r1.id()).unwrap()
+        );
+        assert_eq!(
+            vec![l1.id().clone()],
+            hdl.merge_bases(l1.id(), l2.id()).unwrap()
+        );
+
+        assert!(hdl.is_ancestor(o.id(), l2.id()).unwrap());
+        assert!(hdl.is_ancestor(l2.id(), l2.id()).unwrap());
+        assert!(!hdl.is_ancestor(r1.id(), l2.id()).unwrap());
+
+        assert_eq!(
+            vec![l2.id().clone(), l1.id().clone()],
+            hdl.range(r1.id(), l2.id()).unwrap()
+        );
+
+        // Breadth-first, x is reached through j before l1 is.
+        let j = Remnant::join(&a, &l2, &x);
+        hdl.insert(&j).unwrap();
+        let ids: Vec<NodeId> = hdl
+            .trace(&j)
+            .unwrap()
+            .iter()
+            .map(|r| r.id().clone())
+            .collect();
+        assert_eq!(
+            vec![j.id(), l2.id(), l1.id(), x.id(), o.id()],
+            ids.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn merge_bases_across_joins() {
+        let tdir = tempdir::TempDir::new("ancestry_test").unwrap();
+        let hdl = open(&tdir);
+        let a = Author::new();
+
+        // A criss-cross: j1 and j2 both join l and r, so both l and r
+        // are best common ancestors of j1 and j2.
+        let o = Remnant::origin(&a, "criss-cross");
+        let l = o.append(&a, b"l");
+        let r = o.append(&a, b"r");
+        let j1 = Remnant::join(&a, &l, &r);
+        let j2 = Remnant::join(&a, &r, &l);
+
+        for n in &[&o, &l, &r, &j1, &j2] {
+            hdl.insert(n).unwrap();
+        }
+
+        let mut expected = vec![l.id().clone(), r.id().clone()];
+        expected.sort_by(|a, b| a.bytes().cmp(b.bytes()));
+
+        assert_eq!(expected, hdl.merge_bases(j1.id(), j2.id()).unwrap());
+        assert_eq!(vec![j1.id().clone()], hdl.range(j2.id(), j1.id()).unwrap());
+
+        let timeline = hdl.descendants(o.id()).unwrap();
+        let order = hdl.topological(&timeline).unwrap();
+        assert_eq!(5, order.len());
+        assert_eq!(o.id(), &order[4]);
+        assert!(order[..2].contains(j1.id()) && order[..2].contains(j2.id()));
+
+        // Separate origins share no history.
+        let other = Remnant::origin(&a, "elsewhere");
+        hdl.insert(&other).unwrap();
+        assert!(hdl.merge_bases(j1.id(), other.id()).unwrap().is_empty());
+    }
+}
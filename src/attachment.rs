@@ -0,0 +1,373 @@
+use author::Author;
+use bincode;
+use protocol::{Request, Response};
+use remnant::{build_remnant, Content, Remnant};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sodiumoxide::crypto::hash::sha256 as hash;
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::Read;
+use triefort;
+use util;
+
+/// How much of an attachment each chunk holds.
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+/// The hash of a chunk's data, which is also its key in the store.
+#[derive(PartialEq, Eq, Clone, Hash)]
+pub struct ChunkId(pub hash::Digest);
+
+impl ChunkId {
+    pub fn of(data: &[u8]) -> ChunkId {
+        ChunkId(hash::hash(data))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        let ChunkId(hash::Digest(bytes)) = self;
+        bytes
+    }
+}
+
+impl Serialize for ChunkId {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        util::serialize_hex(s, self.bytes(), &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkId {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<ChunkId, D::Error> {
+        util::deserialize_hex(d, hash::Digest::from_slice).map(ChunkId)
+    }
+}
+
+impl fmt::Debug for ChunkId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ChunkId")?;
+        util::debug_bytes(f, self.bytes())
+    }
+}
+
+impl fmt::Display for ChunkId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        util::display_bytes(f, self.bytes())
+    }
+}
+
+/// A piece of an attachment, stored under the hash of its data.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    id: ChunkId,
+    data: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn new(data: Vec<u8>) -> Chunk {
+        Chunk {
+            id: ChunkId::of(&data),
+            data,
+        }
+    }
+
+    pub fn id(&self) -> &ChunkId {
+        &self.id
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Chunks are only good if their data still hashes to their key.
+impl triefort::Triefort for Chunk {
+    fn key(&self) -> &[u8] {
+        self.id.bytes()
+    }
+
+    fn check(&self, key: &[u8]) -> bool {
+        ChunkId::of(&self.data).bytes() == key
+    }
+}
+
+/// The list of chunks making up an attachment. It's stored as a chunk
+/// itself, and its id is the attachment's root.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Manifest {
+    size: u64,
+    chunks: Vec<ChunkId>,
+}
+
+/// A reference to an attachment: the root of its chunks and its size.
+/// The node holding one commits to both.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub root: ChunkId,
+    pub size: u64,
+}
+
+impl Remnant {
+    /// Create a new Attachment node after this one referring to
+    /// `attachment`.
+    pub fn append_attachment(&self, author: &Author, attachment: &Attachment) -> Remnant {
+        let c = Content::Attachment {
+            parent: self.id().clone(),
+            attachment: attachment.clone(),
+        };
+        build_remnant(author, c)
+    }
+}
+
+/// Split everything `r` yields into chunks and store them, along with
+/// the manifest listing them. Only one chunk is held in memory at a
+/// time. Chunks already in the store are shared rather than stored
+/// again.
+pub fn write<R: Read>(store: &triefort::Handle<Chunk>, mut r: R) -> io::Result<Attachment> {
+    let mut manifest = Manifest {
+        size: 0,
+        chunks: Vec::new(),
+    };
+
+    loop {
+        let mut data = Vec::with_capacity(CHUNK_SIZE);
+        (&mut r).take(CHUNK_SIZE as u64).read_to_end(&mut data)?;
+        if data.is_empty() {
+            break;
+        }
+
+        manifest.size += data.len() as u64;
+        manifest.chunks.push(keep(store, Chunk::new(data))?);
+    }
+
+    let m = Chunk::new(bincode::serialize(&manifest).map_err(invalid)?);
+    Ok(Attachment {
+        root: keep(store, m)?,
+        size: manifest.size,
+    })
+}
+
+fn keep(store: &triefort::Handle<Chunk>, c: Chunk) -> io::Result<ChunkId> {
+    store.insert_new(&c)?;
+    Ok(c.id)
+}
+
+/// The chunks of `attachment` that are in `store`, the manifest first.
+/// Chunks are fetched lazily, so some or all of them may not be here
+/// yet; those are left out for whoever has them to provide.
+pub fn chunks(store: &triefort::Handle<Chunk>, attachment: &Attachment) -> io::Result<Vec<Chunk>> {
+    if !store.contains(attachment.root.bytes()) {
+        return Ok(vec![]);
+    }
+    let manifest = store.get(attachment.root.bytes())?;
+    let m: Manifest = bincode::deserialize(manifest.data()).map_err(invalid)?;
+
+    let mut chunks = vec![manifest];
+    for id in &m.chunks {
+        if store.contains(id.bytes()) {
+            chunks.push(store.get(id.bytes())?);
+        }
+    }
+    Ok(chunks)
+}
+
+/// Store a chunk that came from elsewhere, after checking its data
+/// hashes to its id. Returns false if it was already here.
+pub fn store(store: &triefort::Handle<Chunk>, c: &Chunk) -> io::Result<bool> {
+    if ChunkId::of(&c.data) != c.id {
+        return Err(invalid(format!("chunk {} doesn't match its data", c.id)));
+    }
+    store.insert_new(c)
+}
+
+/// Somewhere to get chunks from.
+pub trait Source {
+    fn chunk(&mut self, id: &ChunkId) -> io::Result<Chunk>;
+}
+
+impl Source for triefort::Handle<Chunk> {
+    fn chunk(&mut self, id: &ChunkId) -> io::Result<Chunk> {
+        self.get(id.bytes())
+    }
+}
+
+impl<S: Source> Source for &mut S {
+    fn chunk(&mut self, id: &ChunkId) -> io::Result<Chunk> {
+        (**self).chunk(id)
+    }
+}
+
+/// Chunks from the local store, asking a peer for any that are
+/// missing. `ask` sends a request and waits for the response. Chunks
+/// from the peer are checked against their hash and kept.
+pub struct Fetching<'a, F> {
+    pub store: &'a triefort::Handle<Chunk>,
+    pub ask: F,
+}
+
+impl<'a, F> Source for Fetching<'a, F>
+where
+    F: FnMut(Request) -> io::Result<Response>,
+{
+    fn chunk(&mut self, id: &ChunkId) -> io::Result<Chunk> {
+        if self.store.contains(id.bytes()) {
+            return self.store.get(id.bytes());
+        }
+
+        match (self.ask)(Request::LookupChunk(id.clone()))? {
+            Response::LookupChunk(Some(c)) if ChunkId::of(&c.data) == *id => {
+                keep(self.store, c.clone())?;
+                Ok(c)
+            }
+            Response::LookupChunk(Some(_)) => Err(invalid(format!("peer sent a bad chunk {}", id))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no one has chunk {}", id),
+            )),
+        }
+    }
+}
+
+/// Reads an attachment back, fetching each chunk only when the read
+/// reaches it.
+pub struct Reader<S> {
+    source: S,
+    chunks: Vec<ChunkId>,
+    next: usize,
+    current: Vec<u8>,
+    pos: usize,
+}
+
+impl<S: Source> Reader<S> {
+    /// Start reading `attachment`. Only the manifest is fetched here.
+    pub fn new(mut source: S, attachment: &Attachment) -> io::Result<Reader<S>> {
+        let m: Manifest =
+            bincode::deserialize(source.chunk(&attachment.root)?.data()).map_err(invalid)?;
+        if m.size != attachment.size {
+            return Err(invalid(format!(
+                "attachment {} is {} bytes, not {}",
+                attachment.root, m.size, attachment.size
+            )));
+        }
+
+        Ok(Reader {
+            source,
+            chunks: m.chunks,
+            next: 0,
+            current: Vec::new(),
+            pos: 0,
+        })
+    }
+}
+
+impl<S: Source> Read for Reader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.current.len() {
+            match self.chunks.get(self.next) {
+                Some(id) => {
+                    self.current = self.source.chunk(id)?.data;
+                    self.pos = 0;
+                    self.next += 1;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let n = cmp::min(buf.len(), self.current.len() - self.pos);
+        buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Answer a peer's request for a chunk from the local store.
+pub fn answer(store: &triefort::Handle<Chunk>, id: &ChunkId) -> Response {
+    Response::LookupChunk(store.get(id.bytes()).ok())
+}
+
+fn invalid<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir;
+
+    fn open(tdir: &tempdir::TempDir) -> triefort::Handle<Chunk> {
+        triefort::open(tdir.path().to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn large_bodies_round_trip_in_chunks() {
+        let tdir = tempdir::TempDir::new("attachment_test").unwrap();
+        let mut store = open(&tdir);
+
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let att = write(&store, &data[..]).unwrap();
+        assert_eq!(data.len() as u64, att.size);
+        // Three chunks of data and the manifest.
+        assert_eq!(4, store.keys().unwrap().len());
+
+        let a = Author::new();
+        let o = Remnant::origin(&a, "files");
+        let t = o.append_attachment(&a, &att);
+        t.verify(&a.partial()).unwrap();
+        assert_eq!(Some(&att), t.content().attachment());
+        assert_eq!(None, t.content().body());
+
+        // A body that looks like a reference is still just a body.
+        let mut lookalike = b"RMNTATCH".to_vec();
+        lookalike.extend(att.root.bytes());
+        lookalike.extend(&att.size.to_le_bytes());
+        assert_eq!(None, o.append(&a, &lookalike).content().attachment());
+
+        let mut back = Vec::new();
+        Reader::new(&mut store, &att)
+            .unwrap()
+            .read_to_end(&mut back)
+            .unwrap();
+        assert_eq!(data, back);
+
+        // The same data again shares every chunk.
+        write(&store, &data[..]).unwrap();
+        assert_eq!(4, store.keys().unwrap().len());
+    }
+
+    #[test]
+    fn missing_chunks_are_fetched_from_a_peer() {
+        let peer_dir = tempdir::TempDir::new("attachment_test").unwrap();
+        let local_dir = tempdir::TempDir::new("attachment_test").unwrap();
+        let peer = open(&peer_dir);
+        let mut local = open(&local_dir);
+
+        let data = vec![7; CHUNK_SIZE + 1];
+        let att = write(&peer, &data[..]).unwrap();
+
+        let mut asked = 0;
+        let mut back = Vec::new();
+        {
+            let source = Fetching {
+                store: &mut local,
+                ask: |req| {
+                    asked += 1;
+                    match req {
+                        Request::LookupChunk(id) => Ok(answer(&peer, &id)),
+                        _ => panic!("unexpected request"),
+                    }
+                },
+            };
+            Reader::new(source, &att)
+                .unwrap()
+                .read_to_end(&mut back)
+                .unwrap();
+        }
+        assert_eq!(data, back);
+        assert_eq!(3, asked);
+        assert_eq!(3, local.keys().unwrap().len());
+    }
+}
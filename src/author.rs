@@ -1,3 +1,4 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sodiumoxide::crypto::hash::sha256 as hash;
 use sodiumoxide::crypto::sign;
 use std::fmt;
@@ -13,7 +14,7 @@ pub struct Author {
 }
 
 /// An author whos secret key is not known.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct PartialAuthor {
     pk: sign::PublicKey,
     id: AuthorId,
@@ -21,16 +22,28 @@ pub struct PartialAuthor {
 
 impl Author {
     pub fn new() -> Author {
-        let id: AuthorId;
         let (pk, sk) = sign::gen_keypair();
-        let &sign::PublicKey(ref pk_bytes) = &pk;
-        id = AuthorId(hash::hash(pk_bytes));
+        let id = AuthorId::from_pk(&pk);
 
-        Author {
-            pk: pk,
-            sk: sk,
-            id: id,
-        }
+        Author { pk, sk, id }
+    }
+
+    /// Derive an author from a seed. The same seed always gives the
+    /// same keys and so the same `AuthorId`.
+    pub fn from_seed(seed: &sign::Seed) -> Author {
+        let (pk, sk) = sign::keypair_from_seed(seed);
+        let id = AuthorId::from_pk(&pk);
+
+        Author { pk, sk, id }
+    }
+
+    /// Rebuild an author from its secret key, which also holds the
+    /// public key.
+    pub fn from_sk(sk: sign::SecretKey) -> Author {
+        let pk = sk.public_key();
+        let id = AuthorId::from_pk(&pk);
+
+        Author { pk, sk, id }
     }
 
     pub fn id(&self) -> &AuthorId {
@@ -41,25 +54,83 @@ impl Author {
         &self.sk
     }
 
+    /// The seed the keys derive from. Every author has one, however
+    /// it was created.
+    pub fn seed(&self) -> sign::Seed {
+        let sign::SecretKey(ref sk) = self.sk;
+        sign::Seed::from_slice(&sk[..sign::SEEDBYTES]).unwrap()
+    }
+
+    pub fn pk(&self) -> &sign::PublicKey {
+        &self.pk
+    }
+
+    /// The public half of this author, suitable for sharing.
+    pub fn partial(&self) -> PartialAuthor {
+        PartialAuthor {
+            pk: self.pk,
+            id: self.id.clone(),
+        }
+    }
+}
+
+impl PartialAuthor {
+    /// The author holding the secret half of `pk`.
+    pub fn from_pk(pk: sign::PublicKey) -> PartialAuthor {
+        PartialAuthor {
+            id: AuthorId::from_pk(&pk),
+            pk,
+        }
+    }
+
+    /// Check that the id really is the hash of the public key. A
+    /// PartialAuthor read from an untrusted source should pass this
+    /// before it's used to verify anything.
+    pub fn check(&self) -> bool {
+        AuthorId::from_pk(&self.pk) == self.id
+    }
+
+    pub fn id(&self) -> &AuthorId {
+        &self.id
+    }
+
     pub fn pk(&self) -> &sign::PublicKey {
         &self.pk
     }
 }
 
 /// An author ID. This is a hash of the Author's public key.
-#[derive(PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Clone, Hash)]
 pub struct AuthorId(pub hash::Digest);
 
 impl AuthorId {
+    /// The id of the author with the public key `pk`.
+    pub fn from_pk(pk: &sign::PublicKey) -> AuthorId {
+        let sign::PublicKey(ref pk_bytes) = pk;
+        AuthorId(hash::hash(pk_bytes))
+    }
+
     pub fn bytes(&self) -> &[u8] {
-        let &AuthorId(hash::Digest(ref bytes)) = self;
+        let AuthorId(hash::Digest(bytes)) = self;
         bytes
     }
 }
 
+impl Serialize for AuthorId {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        util::serialize_hex(s, self.bytes(), &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for AuthorId {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<AuthorId, D::Error> {
+        util::deserialize_hex(d, hash::Digest::from_slice).map(AuthorId)
+    }
+}
+
 impl fmt::Debug for AuthorId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let &AuthorId(hash::Digest(ref bytes)) = self;
+        let AuthorId(hash::Digest(bytes)) = self;
 
         write!(f, "AuthorId")?;
         util::debug_bytes(f, bytes)
@@ -68,7 +139,7 @@ impl fmt::Debug for AuthorId {
 
 impl fmt::Display for AuthorId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let &AuthorId(hash::Digest(ref bytes)) = self;
+        let AuthorId(hash::Digest(bytes)) = self;
         util::display_bytes(f, bytes)
     }
 }
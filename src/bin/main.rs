@@ -1,10 +1,32 @@
 extern crate clap;
 extern crate remnant;
+extern crate serde;
 extern crate serde_json;
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgGroup, SubCommand};
+use remnant::attachment;
+use remnant::author::{Author, PartialAuthor};
+use remnant::bundle;
+use remnant::json;
+use remnant::keyfile::{AuthorFile, SealedAuthor};
+use remnant::keyformat;
+use remnant::keyformat::KeyFormat;
+use remnant::keyring;
+use remnant::log;
+use remnant::mnemonic;
 use remnant::plan;
-use remnant::remnant::Remnant;
+use remnant::private::TimelineKey;
+use remnant::redaction::Redaction;
+use remnant::registry;
+use remnant::remnant::{Metadata, OriginOptions, Remnant};
+use remnant::revocation;
+use remnant::revocation::Revocation;
+use remnant::show;
+use remnant::succession::Succession;
+use remnant::verify;
+use std::fs;
+use std::io;
+use std::io::Read;
 
 fn main() {
     let matches = App::new("remnant")
@@ -17,6 +39,15 @@ fn main() {
                 .required(false)
                 .short("n"),
         )
+        .arg(
+            Arg::with_name("format")
+                .help("how to print results")
+                .required(false)
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .long("format"),
+        )
         .arg(
             Arg::with_name("path")
                 .help("path to remnant database")
@@ -25,6 +56,39 @@ fn main() {
                 .takes_value(true)
                 .short("p"),
         )
+        .arg(
+            Arg::with_name("as")
+                .help("the label or id prefix of the author to sign as")
+                .required(false)
+                .value_name("AUTHOR")
+                .takes_value(true)
+                .long("as"),
+        )
+        .arg(
+            Arg::with_name("passphrase-fd")
+                .help("read the author's passphrase from this file descriptor")
+                .required(false)
+                .value_name("FD")
+                .takes_value(true)
+                .long("passphrase-fd"),
+        )
+        .arg(
+            Arg::with_name("cache-entries")
+                .help("keep up to this many decoded records in memory (default 4096, 0 for none)")
+                .required(false)
+                .value_name("COUNT")
+                .takes_value(true)
+                .long("cache-entries"),
+        )
+        .arg(
+            Arg::with_name("cache-bytes")
+                .help("keep decoded records in memory up to this many stored bytes")
+                .required(false)
+                .value_name("BYTES")
+                .takes_value(true)
+                .long("cache-bytes")
+                .conflicts_with("cache-entries"),
+        )
         .subcommand(
             SubCommand::with_name("append")
                 .about("adds a new record")
@@ -37,10 +101,25 @@ fn main() {
                 )
                 .arg(
                     Arg::with_name("body")
-                        .help("the content to append")
+                        .help("the content to append; read from stdin if neither this nor --file is given")
                         .value_name("BODY")
                         .required(false)
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .help("read the content to append from a file")
+                        .long("file")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .conflicts_with("body"),
+                )
+                .arg(
+                    Arg::with_name("max-body-size")
+                        .help("refuse bodies larger than this many bytes (default 1048576)")
+                        .long("max-body-size")
+                        .value_name("BYTES")
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -51,6 +130,41 @@ fn main() {
                         .help("the origin's name")
                         .value_name("NAME")
                         .required(true),
+                )
+                .arg(owner_only_arg())
+                .arg(member_arg())
+                .arg(
+                    Arg::with_name("deterministic")
+                        .help("leave out the random nonce, so the same author and name always make the same origin")
+                        .long("deterministic"),
+                )
+                .arg(
+                    Arg::with_name("description")
+                        .help("what the timeline is for")
+                        .long("description")
+                        .value_name("TEXT")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("content-type")
+                        .help("how to decode the bodies that follow: a MIME type or schema name")
+                        .long("content-type")
+                        .value_name("TYPE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("tag")
+                        .help("any other metadata; may be given more than once")
+                        .long("tag")
+                        .value_name("KEY=VALUE")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("encrypt")
+                        .help("make the timeline private: only the author and members can read its bodies")
+                        .long("encrypt"),
                 ),
         )
         .subcommand(
@@ -69,44 +183,1265 @@ fn main() {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("attach")
+                .about("adds a new record referring to a file stored in chunks")
+                .arg(
+                    Arg::with_name("parent")
+                        .help("the identifier for the parent record")
+                        .value_name("PARENT")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .help("the file to attach, of any size")
+                        .value_name("FILE")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("attachment")
+                .about("write out the file a record refers to")
+                .arg(
+                    Arg::with_name("node")
+                        .help("the full or abbreviated identifier of the record")
+                        .required(true)
+                        .value_name("NODE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("the file to write; stdout if not given")
+                        .short("o")
+                        .value_name("FILE")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("membership")
+                .about("change who may add to a timeline after a record")
+                .arg(
+                    Arg::with_name("parent")
+                        .help("the identifier for the record the change follows")
+                        .value_name("PARENT")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("open")
+                        .help("let anyone add")
+                        .long("open"),
+                )
+                .arg(owner_only_arg())
+                .arg(member_arg())
+                .group(
+                    ArgGroup::with_name("access")
+                        .args(&["open", "owner-only", "member"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("redact")
+                .about("remove the body of a record, keeping its place in the timeline")
+                .arg(
+                    Arg::with_name("node")
+                        .help("the full or abbreviated identifier of the record")
+                        .required_unless("request")
+                        .value_name("NODE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("reason")
+                        .help("why the body is being removed")
+                        .long("reason")
+                        .value_name("TEXT")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("request")
+                        .help("honor a redaction someone else signed, as printed with --format json")
+                        .long("request")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .conflicts_with_all(&["node", "reason"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge-base")
+                .about("print the best common ancestors of two records")
+                .arg(
+                    Arg::with_name("left")
+                        .required(true)
+                        .value_name("LEFT")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("right")
+                        .required(true)
+                        .value_name("RIGHT")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("is-ancestor")
+                .about("check whether one record is an ancestor of another")
+                .arg(
+                    Arg::with_name("ancestor")
+                        .required(true)
+                        .value_name("ANCESTOR")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("descendant")
+                        .required(true)
+                        .value_name("DESCENDANT")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("range")
+                .about("list records reachable from TO but not from FROM")
+                .arg(
+                    Arg::with_name("from")
+                        .required(true)
+                        .value_name("FROM")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .required(true)
+                        .value_name("TO")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fsck")
+                .about("verify every record in the database and report what's wrong"),
+        )
+        .subcommand(
+            SubCommand::with_name("compress")
+                .about("choose how stored records are compressed and rewrite them to match")
+                .arg(
+                    Arg::with_name("method")
+                        .help("how to compress (default deflate)")
+                        .long("method")
+                        .value_name("METHOD")
+                        .takes_value(true)
+                        .possible_values(&["deflate", "none"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("log")
+                .about("show the records of a timeline")
+                .arg(
+                    Arg::with_name("start")
+                        .help("a head to show the history of, or an origin to show the timeline of")
+                        .required(true)
+                        .value_name("START")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .help("show at most this many records")
+                        .long("limit")
+                        .value_name("N")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("author")
+                        .help("only show records by authors whose id starts with this prefix")
+                        .long("author")
+                        .value_name("AUTHOR")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("since-node")
+                        .help("hide this record and everything before it")
+                        .long("since-node")
+                        .value_name("NODE")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("show")
+                .about("show every field of a record")
+                .arg(
+                    Arg::with_name("node")
+                        .help("the full or abbreviated identifier of the record")
+                        .required(true)
+                        .value_name("NODE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("decrypt")
+                        .help("decrypt the body of a record on a private timeline")
+                        .long("decrypt"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("write timelines to a bundle file")
+                .arg(
+                    Arg::with_name("heads")
+                        .help("heads to export with their history, or origins to export whole")
+                        .required(true)
+                        .multiple(true)
+                        .value_name("NODE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("the bundle file to write")
+                        .required(true)
+                        .short("o")
+                        .value_name("BUNDLE")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("verify and add the records in a bundle file")
+                .arg(
+                    Arg::with_name("input")
+                        .help("the bundle file to read")
+                        .required(true)
+                        .value_name("BUNDLE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("reject-revoked")
+                        .help("refuse bundles holding records signed after their key was revoked")
+                        .long("reject-revoked"),
+                )
+                .arg(
+                    Arg::with_name("remember-authors")
+                        .help("add the bundle's new public keys to the registry as known authors")
+                        .long("remember-authors"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trust")
+                .about("manage which authors are trusted")
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("add a public key to the registry")
+                        .arg(
+                            Arg::with_name("input")
+                                .help("a file holding the key in json, openssh or pem form, or - for stdin")
+                                .required(true)
+                                .value_name("FILE")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("trust")
+                                .help("how far to trust the author (default trusted)")
+                                .long("trust")
+                                .value_name("TRUST")
+                                .takes_value(true)
+                                .possible_values(&["trusted", "known", "blocked"]),
+                        )
+                        .arg(
+                            Arg::with_name("note")
+                                .help("who the author is")
+                                .long("note")
+                                .value_name("TEXT")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("change the trust in an author in the registry")
+                        .arg(
+                            Arg::with_name("author")
+                                .help("a prefix of the author's id")
+                                .required(true)
+                                .value_name("AUTHOR")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("trust")
+                                .required(true)
+                                .value_name("TRUST")
+                                .takes_value(true)
+                                .possible_values(&["trusted", "known", "blocked"]),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("remove an author from the registry")
+                        .arg(
+                            Arg::with_name("author")
+                                .help("a prefix of the author's id")
+                                .required(true)
+                                .value_name("AUTHOR")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("list").about("list the policy and the authors in the registry"),
+                )
+                .subcommand(
+                    SubCommand::with_name("policy")
+                        .about("show or set which authors' records are accepted")
+                        .arg(
+                            Arg::with_name("policy")
+                                .required(false)
+                                .value_name("POLICY")
+                                .takes_value(true)
+                                .possible_values(&["accept-valid", "only-trusted", "reject-blocked"]),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("author")
+                .about("manage the authors in the keyring")
+                .subcommand(
+                    SubCommand::with_name("new")
+                        .about("create a new author")
+                        .arg(
+                            Arg::with_name("label")
+                                .help("the name to refer to the author by")
+                                .required(true)
+                                .value_name("LABEL")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("default")
+                                .help("make the new author the default")
+                                .long("default"),
+                        )
+                        .arg(
+                            Arg::with_name("encrypt")
+                                .help("encrypt the secret key with a passphrase")
+                                .long("encrypt"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("recreate an author from the recovery phrase read from stdin")
+                        .arg(
+                            Arg::with_name("label")
+                                .help("the name to refer to the author by")
+                                .required(true)
+                                .value_name("LABEL")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("default")
+                                .help("make the restored author the default")
+                                .long("default"),
+                        )
+                        .arg(
+                            Arg::with_name("encrypt")
+                                .help("encrypt the secret key with a passphrase")
+                                .long("encrypt"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("mnemonic")
+                        .about("print the recovery phrase of the --as or default author"),
+                )
+                .subcommand(SubCommand::with_name("list").about("list the authors"))
+                .subcommand(
+                    SubCommand::with_name("default")
+                        .about("show or set the default author")
+                        .arg(
+                            Arg::with_name("label")
+                                .help("the label or id prefix of the new default")
+                                .required(false)
+                                .value_name("AUTHOR")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("export-public")
+                        .about("print the public key of the --as or default author")
+                        .arg(
+                            Arg::with_name("output")
+                                .help("write the public key to this file instead")
+                                .short("o")
+                                .value_name("FILE")
+                                .takes_value(true),
+                        )
+                        .arg(key_format_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("convert-public")
+                        .about("read a public key in any supported format and print it in another")
+                        .arg(
+                            Arg::with_name("input")
+                                .help("the file holding the key, or - for stdin")
+                                .required(true)
+                                .value_name("FILE")
+                                .takes_value(true),
+                        )
+                        .arg(key_format_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("encrypt")
+                        .about("encrypt the secret key of the --as or default author"),
+                )
+                .subcommand(
+                    SubCommand::with_name("revoke")
+                        .about("declare the key of the --as or default author compromised")
+                        .arg(
+                            Arg::with_name("last-good")
+                                .help("the last record to keep trusting, with its history")
+                                .long("last-good")
+                                .value_name("NODE")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("certificate")
+                                .help("publish this revocation certificate instead of signing a new one")
+                                .long("certificate")
+                                .value_name("FILE")
+                                .takes_value(true)
+                                .conflicts_with("last-good"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("revocation")
+                        .about("print the revocation certificate made with the --as or default author")
+                        .arg(
+                            Arg::with_name("output")
+                                .help("write the certificate to this file instead")
+                                .short("o")
+                                .value_name("FILE")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("rotate")
+                        .about("replace the key of the --as or default author with a new one"),
+                )
+                .subcommand(
+                    SubCommand::with_name("decrypt")
+                        .about("store the secret key of the --as or default author unencrypted"),
+                ),
+        )
         .get_matches();
 
+    let json = matches.value_of("format") == Some("json");
+
     plan::get_plan(&matches)
-        .map(|mut p| run_plan(&mut p))
-        .map_err(|e| println!("error: {}", e))
+        .and_then(|mut p| run_plan(&mut p))
+        .map_err(|e| {
+            if json {
+                print_json(&json::Error {
+                    error: e.to_string(),
+                })
+            } else {
+                println!("error: {}", e)
+            }
+        })
         .unwrap_or(());
 }
 
-fn run_plan(plan: &mut plan::Plan) {
-    println!("plan: {:?}", plan);
+fn key_format_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("key-format")
+        .help("how to write the public key")
+        .long("key-format")
+        .value_name("FORMAT")
+        .takes_value(true)
+        .possible_values(&["json", "openssh", "pem"])
+}
+
+fn owner_only_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("owner-only")
+        .help("only let the author of the origin add")
+        .long("owner-only")
+        .conflicts_with("member")
+}
+
+fn member_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("member")
+        .help("let this author add as well as the author of the origin; a label or id prefix")
+        .long("member")
+        .value_name("AUTHOR")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+}
+
+fn print_json<T: serde::Serialize>(doc: &T) {
+    println!("{}", serde_json::to_string(doc).unwrap());
+}
+
+fn run_plan(plan: &mut plan::Plan) -> io::Result<()> {
+    let json = plan.format == plan::Format::Json;
+
+    let r = match plan.command.clone() {
+        plan::Command::Append { parent, body } => mk_valid_append(plan, &parent, &body)?,
+        plan::Command::Origin {
+            name,
+            allow,
+            deterministic,
+            meta,
+            encrypt,
+        } => mk_valid_origin(plan, &name, &allow, deterministic, meta, encrypt)?,
+        plan::Command::Join { left, right } => mk_valid_join(plan, &left, &right)?,
+        plan::Command::Attach { parent, file } => mk_valid_attach(plan, &parent, &file)?,
+        plan::Command::Attachment { node, output } => {
+            let n = plan.database.resolve(&node)?;
+            let r = plan.database.get(n.bytes())?;
+            let a = r.content().attachment().map_or_else(
+                || Err(io::Error::other(format!("{} has no attachment", n))),
+                Ok,
+            )?;
+            let mut reader = attachment::Reader::new(&mut plan.chunks, a)?;
+            match output {
+                Some(o) => {
+                    io::copy(&mut reader, &mut io::BufWriter::new(fs::File::create(o)?))?;
+                }
+                None => {
+                    io::copy(&mut reader, &mut io::stdout().lock())?;
+                }
+            }
+            return Ok(());
+        }
+        plan::Command::Membership { parent, allow } => mk_valid_membership(plan, &parent, &allow)?,
+        plan::Command::Redact {
+            node,
+            reason,
+            request,
+        } => {
+            let red = match (request, node) {
+                (Some(f), _) => {
+                    // Either a bare redaction or the output of another
+                    // redact with --format json.
+                    let v: serde_json::Value = serde_json::from_str(&fs::read_to_string(f)?)?;
+                    let v = v.get("redaction").cloned().unwrap_or(v);
+                    serde_json::from_value(v)?
+                }
+                (None, Some(n)) => {
+                    let n = plan.database.resolve(&n)?;
+                    Redaction::new(plan.signer()?, &n, reason.as_deref())
+                }
+                (None, None) => return Err(io::Error::other("nothing to redact")),
+            };
+            let done = plan.database.redact(&red)?;
+            plan.redactions.record(&red)?;
+
+            if json {
+                print_json(&json::Redacted {
+                    redaction: &red,
+                    applied: done,
+                });
+            } else if done {
+                println!("redacted {} by {}", red.node(), red.by().id());
+            } else {
+                println!(
+                    "recorded the redaction of {} by {}; it applies if the record arrives",
+                    red.node(),
+                    red.by().id()
+                );
+            }
+            return Ok(());
+        }
+        plan::Command::MergeBase { left, right } => {
+            let l = plan.database.resolve(&left)?;
+            let r = plan.database.resolve(&right)?;
+            let bases = plan.database.merge_bases(&l, &r)?;
+            if json {
+                print_json(&json::MergeBases {
+                    left: &l,
+                    right: &r,
+                    merge_bases: &bases,
+                });
+            } else {
+                for b in bases {
+                    println!("{}", b);
+                }
+            }
+            return Ok(());
+        }
+        plan::Command::IsAncestor {
+            ancestor,
+            descendant,
+        } => {
+            let a = plan.database.resolve(&ancestor)?;
+            let d = plan.database.resolve(&descendant)?;
+            let is_ancestor = plan.database.is_ancestor(&a, &d)?;
+            if json {
+                print_json(&json::IsAncestor {
+                    ancestor: &a,
+                    descendant: &d,
+                    is_ancestor,
+                });
+            } else {
+                println!("{}", is_ancestor);
+            }
+            return Ok(());
+        }
+        plan::Command::Range { from, to } => {
+            let f = plan.database.resolve(&from)?;
+            let t = plan.database.resolve(&to)?;
+            let nodes = plan.database.range(&f, &t)?;
+            if json {
+                print_json(&json::Range {
+                    from: &f,
+                    to: &t,
+                    nodes: &nodes,
+                });
+            } else {
+                for i in nodes {
+                    println!("{}", i);
+                }
+            }
+            return Ok(());
+        }
+        plan::Command::Show { node, decrypt } => {
+            let n = plan.database.resolve(&node)?;
+            let r = plan.database.get(n.bytes())?;
+            let plain = if decrypt {
+                let signer = plan.signer()?.clone();
+                Some(plan.database.decrypt(&r, &signer)?)
+            } else {
+                None
+            };
+            let authors = plan.known_authors()?;
+            let identity = plan.successions.identity(r.author())?;
+            let revocations = plan.revocations.of(r.author())?;
+            let suspects =
+                revocation::suspects(&plan.database, std::slice::from_ref(&r), &revocations)?;
+            let revoked = suspects.first().map(|(_, rev)| *rev);
+            if json {
+                print_json(&json::Shown {
+                    node: json::Node::new(&r),
+                    identity: &identity,
+                    verified: show::verification(&r, &authors).map(|v| v.is_ok()),
+                    revoked: revoked.is_some(),
+                    decrypted: plain.as_deref().map(json::Decrypted::new),
+                });
+            } else {
+                print!(
+                    "{}",
+                    show::show(&r, &authors, &identity, revoked, plain.as_deref())
+                );
+            }
+            return Ok(());
+        }
+        plan::Command::Export { heads, output } => {
+            let mut ids = Vec::new();
+            for h in heads {
+                ids.push(plan.database.resolve(&h)?);
+            }
+            let authors = plan.known_authors()?;
+            let successions = plan.successions.all()?;
+            let b = bundle::collect(&plan.database, &plan.chunks, &ids, &authors, &successions)?;
+            bundle::write(io::BufWriter::new(fs::File::create(&output)?), &b)?;
+            if json {
+                print_json(&json::Exported {
+                    output: &output,
+                    authors: b.authors.len(),
+                    remnants: b.remnants.len(),
+                    chunks: b.chunks.len(),
+                });
+            } else {
+                println!(
+                    "exported {} records and {} authors to {}",
+                    b.remnants.len(),
+                    b.authors.len(),
+                    output
+                );
+            }
+            return Ok(());
+        }
+        plan::Command::Import {
+            input,
+            reject_revoked,
+            remember_authors,
+        } => {
+            let b = bundle::read(io::BufReader::new(fs::File::open(&input)?))?;
+            let authors = plan.known_authors()?;
+
+            let revocations = plan.revocations.all()?;
+            let suspects = revocation::suspects(&plan.database, &b.remnants, &revocations)?;
+            if reject_revoked && !suspects.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} records were signed with revoked keys, starting with {}",
+                        suspects.len(),
+                        suspects[0].0
+                    ),
+                ));
+            }
+
+            let mut fresh = Vec::new();
+            for r in &b.remnants {
+                if !plan.database.contains(r.id().bytes()) {
+                    fresh.push(r);
+                }
+            }
+            plan.admit(&fresh)?;
+
+            let i = bundle::import(
+                &plan.database,
+                &plan.successions,
+                &plan.chunks,
+                &b,
+                &authors,
+            )?;
+            let remembered = if remember_authors {
+                plan.remember_authors(&b.authors, &format!("imported from {}", input))?
+            } else {
+                0
+            };
+
+            // Bodies redacted here stay redacted when a bundle brings
+            // them back.
+            let (redacted, refused) = plan.database.reapply(&plan.redactions)?;
+            for (red, e) in &refused {
+                eprintln!(
+                    "warning: dropped the redaction of {} by {}: {}",
+                    red.node(),
+                    red.by().id(),
+                    e
+                );
+            }
+
+            if json {
+                print_json(&json::Imported {
+                    counts: &i,
+                    revoked: suspects.iter().map(|(n, _)| n).collect(),
+                    redacted,
+                    remembered,
+                });
+            } else {
+                println!(
+                    "imported {} records, {} already present",
+                    i.imported, i.skipped
+                );
+                if i.successions > 0 {
+                    println!("imported {} key successions", i.successions);
+                }
+                if i.chunks > 0 {
+                    println!("imported {} attachment chunks", i.chunks);
+                }
+                if remembered > 0 {
+                    println!("added {} new authors to the registry as known", remembered);
+                }
+                if redacted > 0 {
+                    println!("redacted {} records again", redacted);
+                }
+                for (n, rev) in &suspects {
+                    println!(
+                        "warning: {} was signed with the revoked key {}",
+                        n,
+                        rev.author().id()
+                    );
+                }
+            }
+            return Ok(());
+        }
+        plan::Command::Log {
+            start,
+            limit,
+            author,
+            since,
+        } => {
+            let s = plan.database.resolve(&start)?;
+            let since = match since {
+                Some(n) => Some(plan.database.resolve(&n)?),
+                None => None,
+            };
+            let opts = log::Options {
+                limit,
+                author,
+                since,
+            };
+            let lines = log::log(&plan.database, &s, &opts)?;
+            if json {
+                let nodes = lines
+                    .iter()
+                    .filter_map(|l| {
+                        l.node.as_ref().map(|r| json::LogNode {
+                            graph: &l.graph,
+                            node: json::Node::new(r),
+                        })
+                    })
+                    .collect();
+                print_json(&json::Log { start: &s, nodes });
+            } else {
+                for l in lines {
+                    match l.node {
+                        Some(ref r) => println!("{} {}", l.graph, log::summary(r)),
+                        None => println!("{}", l.graph),
+                    }
+                }
+            }
+            return Ok(());
+        }
+        plan::Command::Fsck => {
+            let authors = plan.known_authors()?;
+            let report = verify::check(&plan.database, &authors)?;
+            if json {
+                print_json(&report);
+            } else {
+                for p in &report.problems {
+                    println!("{}: {}", p.id, p.problem);
+                }
+                println!(
+                    "checked {} records: {} problems, {} by unknown authors",
+                    report.checked,
+                    report.problems.len(),
+                    report.unknown.len()
+                );
+            }
+            if !report.problems.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} problems found", report.problems.len()),
+                ));
+            }
+            return Ok(());
+        }
+        plan::Command::Compress { method } => {
+            let n = plan.compress(method)?;
+            if json {
+                print_json(&json::Compressed { rewritten: n });
+            } else {
+                println!("rewrote {} stored objects", n);
+            }
+            return Ok(());
+        }
+        plan::Command::AuthorNew { .. }
+        | plan::Command::AuthorList
+        | plan::Command::AuthorDefault { .. }
+        | plan::Command::AuthorExportPublic { .. }
+        | plan::Command::AuthorConvertPublic { .. }
+        | plan::Command::AuthorEncrypt
+        | plan::Command::AuthorDecrypt
+        | plan::Command::AuthorRotate
+        | plan::Command::AuthorMnemonic
+        | plan::Command::AuthorRestore { .. }
+        | plan::Command::AuthorRevoke { .. }
+        | plan::Command::AuthorRevocation { .. } => return run_author(plan),
+        plan::Command::TrustAdd { .. }
+        | plan::Command::TrustSet { .. }
+        | plan::Command::TrustRemove { .. }
+        | plan::Command::TrustList
+        | plan::Command::TrustPolicy { .. } => return run_trust(plan),
+    };
+
+    plan.admit(&[&r])?;
+    plan.database.permits(&r)?;
+    plan.database.insert(&r)?;
+
+    if json {
+        print_json(&json::Created {
+            node: json::Node::new(&r),
+        });
+    } else {
+        println!("{}", log::summary(&r));
+    }
+
+    Ok(())
+}
+
+fn run_author(plan: &mut plan::Plan) -> io::Result<()> {
+    let json = plan.format == plan::Format::Json;
+
+    let label = match plan.command.clone() {
+        plan::Command::AuthorNew {
+            label,
+            default,
+            encrypt,
+        } => {
+            add_author(plan, &label, &Author::new(), default, encrypt)?;
+            label
+        }
+        plan::Command::AuthorRestore {
+            label,
+            default,
+            encrypt,
+        } => {
+            let seed = mnemonic::decode(&plan::read_phrase()?)?;
+            add_author(plan, &label, &Author::from_seed(&seed), default, encrypt)?;
+            label
+        }
+        plan::Command::AuthorMnemonic => {
+            let label = plan.selected()?;
+            let a = plan.keyring.unlock(&label, || {
+                plan.passphrase.read(&format!("passphrase for {}: ", label))
+            })?;
+            let phrase = mnemonic::encode(&a.seed());
+            if json {
+                print_json(&json::Mnemonic {
+                    author: a.id(),
+                    mnemonic: &phrase,
+                });
+            } else {
+                println!("{}", phrase);
+            }
+            return Ok(());
+        }
+        plan::Command::AuthorList => {
+            let entries = plan.keyring.entries()?;
+            if json {
+                print_json(&json::Authors {
+                    authors: entries.iter().map(json::AuthorEntry::new).collect(),
+                });
+            } else {
+                for e in entries {
+                    print_entry(&e);
+                }
+            }
+            return Ok(());
+        }
+        plan::Command::AuthorDefault { label: Some(l) } => {
+            let label = plan.keyring.find(&l)?;
+            plan.keyring.set_default(&label)?;
+            label
+        }
+        plan::Command::AuthorDefault { label: None } => plan.selected()?,
+        plan::Command::AuthorExportPublic { output, key_format } => {
+            let label = plan.selected()?;
+            let public = plan.keyring.load(&label)?.partial();
+            let text = public_text(&public, key_format, &label);
+            match output {
+                Some(o) => fs::write(o, text)?,
+                None => {
+                    print!("{}", text);
+                    return Ok(());
+                }
+            }
+            label
+        }
+        plan::Command::AuthorConvertPublic { input, key_format } => {
+            let text = if input == "-" {
+                let mut s = String::new();
+                io::stdin().read_to_string(&mut s)?;
+                s
+            } else {
+                fs::read_to_string(&input)?
+            };
+            print!("{}", public_text(&keyformat::read(&text)?, key_format, ""));
+            return Ok(());
+        }
+        plan::Command::AuthorEncrypt => {
+            let label = plan.selected()?;
+            plan.keyring.seal(&label, &plan.new_passphrase()?)?;
+            label
+        }
+        plan::Command::AuthorDecrypt => {
+            let label = plan.selected()?;
+            let p = plan
+                .passphrase
+                .read(&format!("passphrase for {}: ", label))?;
+            plan.keyring.unseal(&label, &p)?;
+            label
+        }
+        plan::Command::AuthorRotate => {
+            let label = plan.selected()?;
+            let file = plan.keyring.load(&label)?;
+
+            let p = if file.is_sealed() {
+                Some(
+                    plan.passphrase
+                        .read(&format!("passphrase for {}: ", label))?,
+                )
+            } else {
+                None
+            };
+            let old = file.unlock(|| Ok(p.clone().unwrap_or_default()))?;
+            let new = Author::new();
+
+            // The new key is kept the way the old one was.
+            let new_file = match p {
+                Some(p) => AuthorFile::Sealed(SealedAuthor::seal(&new, p.as_bytes())?),
+                None => AuthorFile::Plain(new.clone()),
+            };
+
+            // Only announce the new key once its secret is saved, but
+            // find out first whether the announcement would be refused.
+            let succession = Succession::new(&old, &new);
+            plan.successions
+                .check_batch(std::slice::from_ref(&succession))?;
+            let retired = plan.keyring.retire(&label, &new_file)?;
+            plan.keyring
+                .save_revocation(&label, &Revocation::new(&new, None))?;
+            plan.successions.record(&succession)?;
+            let identity = plan.successions.identity(new.id())?;
 
-    let r = match &plan.command {
-        &plan::Command::Append {
-            parent: ref p,
-            body: ref b,
-        } => mk_valid_append(plan, p, b),
-        &plan::Command::Origin { name: ref n } => mk_valid_origin(plan, n),
-        &plan::Command::Join {
-            left: ref l,
-            right: ref r,
-        } => mk_valid_join(plan, l, r),
+            let entries = plan.keyring.entries()?;
+            let e = entries
+                .iter()
+                .find(|e| e.label == label)
+                .ok_or_else(|| io::Error::other("author vanished"))?;
+            if json {
+                print_json(&json::Rotated {
+                    author: json::AuthorEntry::new(e),
+                    retired: &retired,
+                    identity: &identity,
+                });
+            } else {
+                print_entry(e);
+                println!("retired {} as {}", old.id(), retired);
+            }
+            return Ok(());
+        }
+        plan::Command::AuthorRevoke {
+            last_good,
+            certificate,
+        } => {
+            let rev = match (certificate, last_good) {
+                (Some(c), None) => serde_json::from_str(&fs::read_to_string(c)?)?,
+                (None, n) => {
+                    let n = match n {
+                        Some(n) => Some(plan.database.resolve(&n)?),
+                        None => None,
+                    };
+                    let label = plan.selected()?;
+                    let a = plan.keyring.unlock(&label, || {
+                        plan.passphrase.read(&format!("passphrase for {}: ", label))
+                    })?;
+                    Revocation::new(&a, n.as_ref())
+                }
+                (Some(_), Some(_)) => {
+                    return Err(io::Error::other(
+                        "a certificate already names its last good node",
+                    ))
+                }
+            };
+            plan.revocations.record(&rev)?;
+
+            if json {
+                print_json(&rev);
+            } else {
+                match rev.last_good() {
+                    Some(n) => println!("revoked {} after {}", rev.author().id(), n),
+                    None => println!("revoked {}", rev.author().id()),
+                }
+            }
+            return Ok(());
+        }
+        plan::Command::AuthorRevocation { output } => {
+            let label = plan.selected()?;
+            let cert = serde_json::to_string(&plan.keyring.revocation(&label)?)?;
+            match output {
+                Some(o) => fs::write(o, cert + "\n")?,
+                None => println!("{}", cert),
+            }
+            return Ok(());
+        }
+        c => return Err(io::Error::other(format!("not an author command: {:?}", c))),
     };
 
-    println!("remnant: {:?}", r);
+    let entries = plan.keyring.entries()?;
+    let e = entries
+        .iter()
+        .find(|e| e.label == label)
+        .ok_or_else(|| io::Error::other("author vanished"))?;
+    if json {
+        print_json(&json::AuthorEntry::new(e));
+    } else {
+        print_entry(e);
+    }
 
-    plan.database.insert(&r).unwrap();
+    Ok(())
+}
 
-    println!("trace: {:?}", plan.database.trace(&r));
+fn run_trust(plan: &mut plan::Plan) -> io::Result<()> {
+    let json = plan.format == plan::Format::Json;
+
+    let changed = match plan.command.clone() {
+        plan::Command::TrustAdd { input, trust, note } => {
+            let text = if input == "-" {
+                let mut s = String::new();
+                io::stdin().read_to_string(&mut s)?;
+                s
+            } else {
+                fs::read_to_string(&input)?
+            };
+            let a = keyformat::read(&text)?;
+            let id = a.id().clone();
+            plan.registry.set(a, trust, note)?;
+            Some(id)
+        }
+        plan::Command::TrustSet { author, trust } => {
+            let a = plan.registry.find(&author)?.author.clone();
+            let id = a.id().clone();
+            plan.registry.set(a, trust, None)?;
+            Some(id)
+        }
+        plan::Command::TrustRemove { author } => {
+            let id = plan.registry.find(&author)?.author.id().clone();
+            plan.registry.remove(&id);
+            plan.registry.save()?;
+            if json {
+                print_json(&json::Removed { author: &id });
+            } else {
+                println!("removed {}", id);
+            }
+            return Ok(());
+        }
+        plan::Command::TrustPolicy { policy: Some(p) } => {
+            plan.registry.set_policy(p);
+            None
+        }
+        plan::Command::TrustList | plan::Command::TrustPolicy { policy: None } => None,
+        c => return Err(io::Error::other(format!("not a trust command: {:?}", c))),
+    };
+    plan.registry.save()?;
+
+    let entries: Vec<&registry::Entry> = match changed {
+        Some(ref id) => plan.registry.get(id).into_iter().collect(),
+        None => plan.registry.entries().iter().collect(),
+    };
+
+    if json {
+        match changed {
+            Some(_) => print_json(&json::TrustEntry::new(entries[0])),
+            None => print_json(&json::Registry {
+                policy: plan.registry.policy(),
+                authors: entries.into_iter().map(json::TrustEntry::new).collect(),
+            }),
+        }
+    } else {
+        if changed.is_none() {
+            println!("policy {}", plan.registry.policy().name());
+        }
+        for e in entries {
+            println!(
+                "{:8} {}{}",
+                e.trust.name(),
+                e.author.id(),
+                e.note.as_ref().map_or(String::new(), |n| format!(" {}", n))
+            );
+        }
+    }
+
+    Ok(())
 }
 
-fn mk_valid_append(_plan: &plan::Plan, _parent: &str, _body: &[u8]) -> Remnant {
-    panic!("mk_valid_append")
+/// Add an author to the keyring along with a revocation certificate
+/// for it. The secret key is sealed if asked to, or if a passphrase
+/// was supplied without prompting.
+fn add_author(
+    plan: &plan::Plan,
+    label: &str,
+    a: &Author,
+    default: bool,
+    encrypt: bool,
+) -> io::Result<()> {
+    let file = if encrypt || !plan.passphrase.is_interactive() {
+        let p = plan.new_passphrase()?;
+        AuthorFile::Sealed(SealedAuthor::seal(a, p.as_bytes())?)
+    } else {
+        AuthorFile::Plain(a.clone())
+    };
+
+    plan.keyring.add(label, &file)?;
+    plan.keyring
+        .save_revocation(label, &Revocation::new(a, None))?;
+    if default {
+        plan.keyring.set_default(label)?;
+    }
+    Ok(())
+}
+
+/// A public key in `key_format`, ending with a newline.
+fn public_text(public: &PartialAuthor, key_format: KeyFormat, comment: &str) -> String {
+    let mut text = keyformat::write(public, key_format, comment);
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// One line per author: a star for the default, the label, the id and
+/// whether the secret key is encrypted.
+fn print_entry(e: &keyring::Entry) {
+    println!(
+        "{} {:16} {}{}",
+        if e.default { "*" } else { " " },
+        e.label,
+        e.author.id(),
+        if e.sealed { " (encrypted)" } else { "" }
+    );
+}
+
+fn mk_valid_append(plan: &mut plan::Plan, parent: &str, body: &[u8]) -> io::Result<Remnant> {
+    let p = plan.database.resolve(parent)?;
+    let p = plan.database.get(p.bytes())?;
+    let signer = plan.signer()?.clone();
+    match plan.database.timeline_key(p.id(), &signer)? {
+        Some(k) => Ok(p.append_private(&signer, &k, body)),
+        None => Ok(p.append(&signer, body)),
+    }
 }
 
-fn mk_valid_origin(plan: &plan::Plan, name: &str) -> Remnant {
-    Remnant::origin(&plan.author, name)
+fn mk_valid_origin(
+    plan: &plan::Plan,
+    name: &str,
+    allow: &plan::Allow,
+    deterministic: bool,
+    meta: Metadata,
+    encrypt: bool,
+) -> io::Result<Remnant> {
+    let mut opts = if deterministic {
+        OriginOptions::default()
+    } else {
+        OriginOptions::unique()
+    };
+    opts.access = plan.access(allow)?;
+    opts.meta = meta;
+    if encrypt {
+        let mut readers = vec![plan.signer()?.partial()];
+        if let plan::Allow::Members(m) = allow {
+            for s in m {
+                readers.push(plan.public_key(s)?);
+            }
+        }
+        opts.keys = TimelineKey::new().seal_for(&readers)?;
+    }
+    Ok(Remnant::origin_with(plan.signer()?, name, opts))
+}
+
+fn mk_valid_join(plan: &mut plan::Plan, left: &str, right: &str) -> io::Result<Remnant> {
+    let l = plan.database.resolve(left)?;
+    let l = plan.database.get(l.bytes())?;
+    let r = plan.database.resolve(right)?;
+    let r = plan.database.get(r.bytes())?;
+    Ok(Remnant::join(plan.signer()?, &l, &r))
+}
+
+fn mk_valid_attach(plan: &mut plan::Plan, parent: &str, file: &str) -> io::Result<Remnant> {
+    let p = plan.database.resolve(parent)?;
+    let p = plan.database.get(p.bytes())?;
+    let signer = plan.signer()?.clone();
+
+    // Chunks are stored as they are, so they'd give away what a
+    // private timeline keeps to its readers.
+    if plan.database.timeline_key(p.id(), &signer)?.is_some() {
+        return Err(io::Error::other(
+            "attachments aren't encrypted, so they can't be added to a private timeline",
+        ));
+    }
+
+    let a = attachment::write(&plan.chunks, io::BufReader::new(fs::File::open(file)?))?;
+    Ok(p.append_attachment(&signer, &a))
 }
 
-fn mk_valid_join(_plan: &plan::Plan, _left: &str, _right: &str) -> Remnant {
-    panic!("mk_valid_join")
+fn mk_valid_membership(
+    plan: &mut plan::Plan,
+    parent: &str,
+    allow: &plan::Allow,
+) -> io::Result<Remnant> {
+    let access = plan.access(allow)?;
+    let p = plan.database.resolve(parent)?;
+    let p = plan.database.get(p.bytes())?;
+    if plan.database.is_private(p.id())? {
+        eprintln!(
+            "warning: {} is on a private timeline, whose readers are fixed by its origin: \
+             new members won't be able to read it",
+            p.id()
+        );
+    }
+    Ok(p.membership(plan.signer()?, access))
 }
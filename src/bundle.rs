@@ -0,0 +1,494 @@
+use access;
+use attachment;
+use attachment::{Chunk, ChunkId};
+use author::{AuthorId, PartialAuthor};
+use bincode;
+use remnant::{NodeId, Remnant};
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::io;
+use std::io::{Read, Write};
+use succession::Succession;
+use triefort;
+use util;
+use verify;
+
+/// The first bytes of every bundle file.
+pub const MAGIC: &[u8; 8] = b"RMNTBNDL";
+
+/// The bundle format this code writes. Version 1 bundles, which have
+/// no successions, and version 2 bundles, which have no chunks, can
+/// still be read.
+pub const VERSION: u32 = 3;
+
+/// A portable set of Remnants along with the public keys needed to
+/// verify them. Remnants are ordered so that parents come before
+/// their children.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bundle {
+    pub authors: Vec<PartialAuthor>,
+    pub remnants: Vec<Remnant>,
+
+    /// Key rotations by the authors of the Remnants.
+    pub successions: Vec<Succession>,
+
+    /// The chunks of every attachment among the Remnants, so they can
+    /// be opened wherever the bundle goes.
+    pub chunks: Vec<Chunk>,
+}
+
+#[derive(Deserialize)]
+struct BundleV1 {
+    authors: Vec<PartialAuthor>,
+    remnants: Vec<Remnant>,
+}
+
+#[derive(Deserialize)]
+struct BundleV2 {
+    authors: Vec<PartialAuthor>,
+    remnants: Vec<Remnant>,
+    successions: Vec<Succession>,
+}
+
+/// What happened when a bundle was imported.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Imported {
+    /// Remnants that were new to the database.
+    pub imported: usize,
+
+    /// Remnants the database already had.
+    pub skipped: usize,
+
+    /// Successions that were new to the database.
+    pub successions: usize,
+
+    /// Attachment chunks that were new to the chunk store.
+    pub chunks: usize,
+}
+
+/// Gather everything reachable from `heads` into a bundle. A head
+/// that is an Origin brings its whole timeline along. Public keys are
+/// included for each of `authors` that wrote one of the Remnants, as
+/// are the `successions` retiring or introducing one of their keys
+/// and whichever chunks of their attachments are stored here.
+pub fn collect(
+    db: &triefort::Handle<Remnant>,
+    chunk_store: &triefort::Handle<Chunk>,
+    heads: &[NodeId],
+    authors: &[PartialAuthor],
+    successions: &[Succession],
+) -> io::Result<Bundle> {
+    let mut tips = Vec::new();
+    for h in heads {
+        if db.get(h.bytes())?.content().parents().is_empty() {
+            tips.extend(db.descendants(h)?);
+        } else {
+            tips.push(h.clone());
+        }
+    }
+
+    // Joins can reach into other timelines, so take the ancestors of
+    // everything rather than assuming the origin covers it.
+    let mut seen = HashSet::new();
+    let mut nodes = Vec::new();
+    for t in &tips {
+        for i in db.ancestors(t)? {
+            if seen.insert(i.clone()) {
+                nodes.push(i);
+            }
+        }
+    }
+
+    let mut order = db.topological(&nodes)?;
+    order.reverse();
+
+    let mut remnants = Vec::with_capacity(order.len());
+    for i in order {
+        remnants.push(db.get(i.bytes())?);
+    }
+
+    let used: HashSet<&AuthorId> = remnants.iter().map(|r| r.author()).collect();
+    let authors = authors
+        .iter()
+        .filter(|a| used.contains(a.id()))
+        .cloned()
+        .collect();
+    let successions = successions
+        .iter()
+        .filter(|s| used.contains(s.retired().id()) || used.contains(s.replacement().id()))
+        .cloned()
+        .collect();
+
+    let mut seen_chunks: HashSet<ChunkId> = HashSet::new();
+    let mut chunks = Vec::new();
+    for r in &remnants {
+        if let Some(a) = r.content().attachment() {
+            for c in attachment::chunks(chunk_store, a)? {
+                if seen_chunks.insert(c.id().clone()) {
+                    chunks.push(c);
+                }
+            }
+        }
+    }
+
+    Ok(Bundle {
+        authors,
+        remnants,
+        successions,
+        chunks,
+    })
+}
+
+/// Write a bundle: the magic bytes, the format version, and then the
+/// bundle itself in bincode.
+pub fn write<W: Write>(mut w: W, bundle: &Bundle) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    bincode::serialize_into(&mut w, bundle).map_err(invalid)?;
+    w.flush()
+}
+
+/// Read a bundle written by `write`.
+pub fn read<R: Read>(mut r: R) -> io::Result<Bundle> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a remnant bundle"));
+    }
+
+    let mut version = [0; 4];
+    r.read_exact(&mut version)?;
+    match u32::from_le_bytes(version) {
+        1 => {
+            let b: BundleV1 = bincode::deserialize_from(r).map_err(invalid)?;
+            Ok(Bundle {
+                authors: b.authors,
+                remnants: b.remnants,
+                successions: vec![],
+                chunks: vec![],
+            })
+        }
+        2 => {
+            let b: BundleV2 = bincode::deserialize_from(r).map_err(invalid)?;
+            Ok(Bundle {
+                authors: b.authors,
+                remnants: b.remnants,
+                successions: b.successions,
+                chunks: vec![],
+            })
+        }
+        VERSION => bincode::deserialize_from(r).map_err(invalid),
+        v => Err(invalid(format!("unsupported bundle version: {}", v))),
+    }
+}
+
+/// Verify every Remnant, succession and chunk in the bundle and then
+/// insert the ones the stores don't already have. Signatures are checked
+/// against the bundle's public keys, the keys named in its
+/// successions, and `known`, and every Remnant must be allowed by the
+/// access of its timeline. Nothing is inserted unless the whole
+/// bundle verifies, and the error names every Remnant that didn't.
+pub fn import(
+    db: &triefort::Handle<Remnant>,
+    successions: &triefort::Handle<Succession>,
+    chunk_store: &triefort::Handle<Chunk>,
+    bundle: &Bundle,
+    known: &[PartialAuthor],
+) -> io::Result<Imported> {
+    successions.check_batch(&bundle.successions)?;
+
+    let named = bundle
+        .successions
+        .iter()
+        .flat_map(|s| vec![s.retired(), s.replacement()]);
+
+    let mut authors: HashMap<&AuthorId, &PartialAuthor> = HashMap::new();
+    for a in known.iter().chain(bundle.authors.iter()).chain(named) {
+        if !a.check() {
+            return Err(invalid(format!("bad public key for author {}", a.id())));
+        }
+        authors.insert(a.id(), a);
+    }
+
+    let mut pairs = Vec::with_capacity(bundle.remnants.len());
+    for r in &bundle.remnants {
+        let a = authors
+            .get(r.author())
+            .ok_or_else(|| invalid(format!("unknown author {} of {}", r.author(), r.id())))?;
+        pairs.push((r, *a));
+    }
+
+    let failed = verify::verify_all(&pairs);
+    if !failed.is_empty() {
+        let list: Vec<String> = failed
+            .iter()
+            .map(|f| format!("{}: {:?}", util::to_hex(f.id.bytes()), f.err))
+            .collect();
+        return Err(invalid(format!(
+            "{} records failed to verify: {}",
+            failed.len(),
+            list.join(", ")
+        )));
+    }
+
+    let mut present = HashSet::new();
+    for r in &bundle.remnants {
+        for p in r.content().parents() {
+            if !present.contains(p) && !db.contains(p.bytes()) {
+                return Err(invalid(format!("{} is missing its parent {}", r.id(), p)));
+            }
+        }
+        present.insert(r.id().clone());
+    }
+    access::check(db, &bundle.remnants)?;
+
+    for c in &bundle.chunks {
+        if ChunkId::of(c.data()) != *c.id() {
+            return Err(invalid(format!("chunk {} doesn't match its data", c.id())));
+        }
+    }
+
+    let mut imported = Imported::default();
+    for s in &bundle.successions {
+        if successions.record(s)? {
+            imported.successions += 1;
+        }
+    }
+    for c in &bundle.chunks {
+        if attachment::store(chunk_store, c)? {
+            imported.chunks += 1;
+        }
+    }
+    for r in &bundle.remnants {
+        if db.contains(r.id().bytes()) {
+            imported.skipped += 1;
+        } else {
+            db.insert(r)?;
+            imported.imported += 1;
+        }
+    }
+
+    Ok(imported)
+}
+
+fn invalid<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+    use registry::{Registry, Trust};
+    use tempdir;
+
+    fn open<T: triefort::Triefort>(tdir: &tempdir::TempDir, name: &str) -> triefort::Handle<T> {
+        triefort::open(tdir.path().join(name).to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trip_between_databases() {
+        let src_dir = tempdir::TempDir::new("bundle_test").unwrap();
+        let dst_dir = tempdir::TempDir::new("bundle_test").unwrap();
+        let src: triefort::Handle<Remnant> = open(&src_dir, "database");
+        let src_chunks = open(&src_dir, "chunks");
+        let dst = open(&dst_dir, "database");
+        let dst_successions = open(&dst_dir, "successions");
+        let mut dst_chunks = open(&dst_dir, "chunks");
+
+        let a = Author::new();
+        let b = Author::new();
+        let o = Remnant::origin(&a, "moving");
+        let l = o.append(&a, b"left");
+        let r = o.append(&b, b"right");
+        let j = Remnant::join(&a, &l, &r);
+        let data = vec![9; attachment::CHUNK_SIZE + 1];
+        let f = j.append_attachment(&a, &attachment::write(&src_chunks, &data[..]).unwrap());
+
+        for n in &[&o, &l, &r, &j, &f] {
+            src.insert(n).unwrap();
+        }
+
+        // b has since rotated to c, and only c's key is still at hand.
+        let c = Author::new();
+        let s = Succession::new(&b, &c);
+
+        let bundle = collect(
+            &src,
+            &src_chunks,
+            &[o.id().clone()],
+            &[a.partial(), c.partial()],
+            std::slice::from_ref(&s),
+        )
+        .unwrap();
+        assert_eq!(o, bundle.remnants[0]);
+        assert_eq!(j, bundle.remnants[3]);
+        assert_eq!(1, bundle.authors.len());
+        assert_eq!(vec![s.clone()], bundle.successions);
+        // Two chunks of data and the manifest.
+        assert_eq!(3, bundle.chunks.len());
+
+        let mut file = Vec::new();
+        write(&mut file, &bundle).unwrap();
+        let bundle = read(&file[..]).unwrap();
+
+        assert_eq!(
+            Imported {
+                imported: 5,
+                skipped: 0,
+                successions: 1,
+                chunks: 3,
+            },
+            import(&dst, &dst_successions, &dst_chunks, &bundle, &[]).unwrap()
+        );
+        assert_eq!(j, dst.get(j.id().bytes()).unwrap());
+        let mut back = Vec::new();
+        attachment::Reader::new(&mut dst_chunks, f.content().attachment().unwrap())
+            .unwrap()
+            .read_to_end(&mut back)
+            .unwrap();
+        assert_eq!(data, back);
+        assert_eq!(*b.id(), dst_successions.identity(c.id()).unwrap());
+
+        assert_eq!(
+            Imported {
+                imported: 0,
+                skipped: 5,
+                successions: 0,
+                chunks: 0,
+            },
+            import(&dst, &dst_successions, &dst_chunks, &bundle, &[]).unwrap()
+        );
+
+        // Chunks not fetched yet are left for the receiver to fetch.
+        assert!(src_chunks.remove(bundle.chunks[1].id().bytes()).unwrap());
+        let partial = collect(&src, &src_chunks, &[o.id().clone()], &[], &[]).unwrap();
+        assert_eq!(5, partial.remnants.len());
+        assert_eq!(2, partial.chunks.len());
+    }
+
+    #[test]
+    fn rejects_unverifiable_bundles() {
+        let tdir = tempdir::TempDir::new("bundle_test").unwrap();
+        let db = open(&tdir, "database");
+        let successions = open(&tdir, "successions");
+        let chunks = open(&tdir, "chunks");
+
+        let a = Author::new();
+        let o = Remnant::origin(&a, "orphan");
+        let t = o.append(&a, b"no parent");
+
+        let missing_parent = Bundle {
+            authors: vec![a.partial()],
+            remnants: vec![t],
+            successions: vec![],
+            chunks: vec![],
+        };
+        assert!(import(&db, &successions, &chunks, &missing_parent, &[]).is_err());
+
+        let unknown_author = Bundle {
+            authors: vec![],
+            remnants: vec![o.clone()],
+            successions: vec![],
+            chunks: vec![],
+        };
+        assert!(import(&db, &successions, &chunks, &unknown_author, &[]).is_err());
+        assert!(db.keys().unwrap().is_empty());
+
+        // Every record that fails is named, not just the first.
+        let forge = |r: &Remnant| {
+            let mut bytes = bincode::serialize(r).unwrap();
+            *bytes.last_mut().unwrap() ^= 1;
+            bincode::deserialize::<Remnant>(&bytes).unwrap()
+        };
+        let u = o.append(&a, b"fine");
+        let forged = Bundle {
+            authors: vec![a.partial()],
+            remnants: vec![forge(&o), u.clone(), forge(&u.append(&a, b"forged"))],
+            successions: vec![],
+            chunks: vec![],
+        };
+        let e = import(&db, &successions, &chunks, &forged, &[])
+            .unwrap_err()
+            .to_string();
+        assert!(e.starts_with("2 records failed to verify"));
+        for r in &[&forged.remnants[0], &forged.remnants[2]] {
+            assert!(e.contains(&util::to_hex(r.id().bytes())));
+        }
+        assert!(!e.contains(&util::to_hex(u.id().bytes())));
+        assert!(db.keys().unwrap().is_empty());
+
+        // a's key already has a different successor here.
+        let (b, c) = (Author::new(), Author::new());
+        successions.record(&Succession::new(&a, &b)).unwrap();
+        let conflicting = Bundle {
+            authors: vec![a.partial()],
+            remnants: vec![o.clone()],
+            successions: vec![Succession::new(&c, &Author::new()), Succession::new(&a, &c)],
+            chunks: vec![],
+        };
+        assert!(import(&db, &successions, &chunks, &conflicting, &[]).is_err());
+        assert!(db.keys().unwrap().is_empty());
+        assert_eq!(1, successions.keys().unwrap().len());
+
+        let mut bytes = bincode::serialize(&Chunk::new(b"promised".to_vec())).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        let bad_chunk: Chunk = bincode::deserialize(&bytes).unwrap();
+        let tampered = Bundle {
+            authors: vec![a.partial()],
+            remnants: vec![o.clone()],
+            successions: vec![],
+            chunks: vec![bad_chunk],
+        };
+        assert!(import(&db, &successions, &chunks, &tampered, &[]).is_err());
+        assert!(db.keys().unwrap().is_empty());
+
+        assert!(read(&b"not a bundle at all"[..]).is_err());
+    }
+
+    #[test]
+    fn imported_keys_travel_on() {
+        let dirs: Vec<_> = (0..3)
+            .map(|_| tempdir::TempDir::new("bundle_test").unwrap())
+            .collect();
+        let dbs: Vec<triefort::Handle<Remnant>> =
+            dirs.iter().map(|d| open(d, "database")).collect();
+        let successions: Vec<triefort::Handle<Succession>> =
+            dirs.iter().map(|d| open(d, "successions")).collect();
+        let chunks: Vec<triefort::Handle<Chunk>> = dirs.iter().map(|d| open(d, "chunks")).collect();
+
+        let a = Author::new();
+        let o = Remnant::origin(&a, "relay");
+        let t = o.append(&a, b"pass it on");
+        dbs[0].insert(&o).unwrap();
+        dbs[0].insert(&t).unwrap();
+
+        let first = collect(&dbs[0], &chunks[0], &[t.id().clone()], &[a.partial()], &[]).unwrap();
+        import(&dbs[1], &successions[1], &chunks[1], &first, &[]).unwrap();
+
+        // The middle database only knows a through its registry.
+        let mut registry = Registry::open(&dirs[1].path().join("registry.json")).unwrap();
+        assert_eq!(1, registry.remember(&first.authors, "relayed").unwrap());
+        assert_eq!(0, registry.remember(&first.authors, "relayed").unwrap());
+        assert_eq!(Some(Trust::Known), registry.trust(&[a.id().clone()]));
+
+        let second = collect(
+            &dbs[1],
+            &chunks[1],
+            &[t.id().clone()],
+            &registry.partials(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(
+            2,
+            import(&dbs[2], &successions[2], &chunks[2], &second, &[])
+                .unwrap()
+                .imported
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use bincode;
+use ciborium;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use std::error;
+use std::io;
+
+/// A way of turning typed values into the bytes of a `Body` and back.
+pub trait Codec {
+    /// The content type an Origin can declare so readers know to use
+    /// this codec.
+    fn content_type(&self) -> &'static str;
+
+    fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>>;
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> io::Result<T>;
+}
+
+/// JSON, for bodies people and other tools will read.
+#[derive(Debug, Clone, Copy)]
+pub struct Json;
+
+/// Bincode, the most compact, but only readable with the same types.
+#[derive(Debug, Clone, Copy)]
+pub struct Bincode;
+
+/// CBOR: compact, but still self-describing.
+#[derive(Debug, Clone, Copy)]
+pub struct Cbor;
+
+impl Codec for Json {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(invalid)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> io::Result<T> {
+        serde_json::from_slice(bytes).map_err(invalid)
+    }
+}
+
+impl Codec for Bincode {
+    fn content_type(&self) -> &'static str {
+        "application/x-bincode"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        bincode::serialize(value).map_err(invalid)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> io::Result<T> {
+        bincode::deserialize(bytes).map_err(invalid)
+    }
+}
+
+impl Codec for Cbor {
+    fn content_type(&self) -> &'static str {
+        "application/cbor"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).map_err(|e| invalid(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> io::Result<T> {
+        ciborium::from_reader(bytes).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+fn invalid<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+    use remnant::Remnant;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Event {
+        kind: String,
+        count: u32,
+        tags: Vec<String>,
+    }
+
+    fn round_trip<C: Codec + Copy>(codec: C) {
+        let a = Author::new();
+        let o = Remnant::origin(&a, "events");
+        let e = Event {
+            kind: "opened".to_string(),
+            count: 3,
+            tags: vec!["door".to_string()],
+        };
+
+        let t = o.append_typed(&a, codec, &e).unwrap();
+        t.verify(&a.partial()).unwrap();
+        assert_eq!(e, t.body_as::<Event, _>(codec).unwrap());
+
+        assert!(o.body_as::<Event, _>(codec).is_err());
+        assert!(codec.decode::<Event>(b"\xff\xff").is_err());
+    }
+
+    #[test]
+    fn typed_bodies_round_trip() {
+        round_trip(Json);
+        round_trip(Bincode);
+        round_trip(Cbor);
+
+        let a = Author::new();
+        let t = Remnant::origin(&a, "events")
+            .append_typed(&a, Json, &vec![1, 2])
+            .unwrap();
+        assert_eq!(Some(&b"[1,2]"[..]), t.content().body().map(|b| b.bytes()));
+    }
+}
@@ -0,0 +1,336 @@
+use access::Access;
+use attachment::Attachment;
+use author::AuthorId;
+use bundle;
+use keyring;
+use redaction::Redaction;
+use registry;
+use remnant::{Content, Metadata, NodeId, Remnant, Signature};
+use util;
+
+/// A Remnant as it appears in JSON output. Every field is always
+/// present; fields that don't apply to the content kind are null.
+#[derive(Debug, Serialize)]
+pub struct Node<'a> {
+    pub id: &'a NodeId,
+    pub author: &'a AuthorId,
+    pub signature: &'a Signature,
+
+    /// One of `origin`, `append`, `join`, `membership`, `attachment`
+    /// or `redacted`.
+    /// A redacted node is an Append whose body has been removed.
+    pub kind: &'static str,
+    pub parents: Vec<&'a NodeId>,
+
+    /// The name of an Origin.
+    pub name: Option<&'a str>,
+
+    /// The nonce of an Origin as hex, if it has one.
+    pub nonce: Option<String>,
+
+    /// Who may add after an Origin or Membership: `"open"`,
+    /// `"owner"`, or `{"members": [...]}`.
+    pub access: Option<&'a Access>,
+
+    /// The description, content type and tags of an Origin.
+    pub meta: Option<&'a Metadata>,
+
+    /// Who can decrypt the bodies of a private timeline, for an
+    /// Origin. Empty if the timeline isn't private.
+    pub readers: Option<Vec<&'a AuthorId>>,
+
+    /// The body of an Append as hex.
+    pub body: Option<String>,
+
+    /// The body of an Append as text, if it's UTF-8.
+    pub text: Option<&'a str>,
+
+    /// The SHA-256 of the body of an Append as hex. Redacted nodes
+    /// keep it after the body is gone.
+    pub digest: Option<String>,
+
+    /// The root and size of an Attachment's file.
+    pub attachment: Option<&'a Attachment>,
+}
+
+impl<'a> Node<'a> {
+    pub fn new(r: &'a Remnant) -> Node<'a> {
+        let (name, access, body, text) = match r.content() {
+            Content::Origin {
+                name: n, access: a, ..
+            } => (Some(&n[..]), Some(a), None, None),
+            Content::Append { body: b, .. } => (
+                None,
+                None,
+                Some(util::to_hex(b.bytes())),
+                ::std::str::from_utf8(b.bytes()).ok(),
+            ),
+            Content::Join { .. } | Content::Attachment { .. } | Content::Redacted { .. } => {
+                (None, None, None, None)
+            }
+            Content::Membership { access: a, .. } => (None, Some(a), None, None),
+        };
+
+        let (nonce, meta, readers) = match r.content() {
+            Content::Origin {
+                nonce: n,
+                meta: m,
+                keys: k,
+                ..
+            } => (
+                n.map(|n| util::to_hex(&n)),
+                Some(m),
+                Some(k.iter().map(|k| &k.member).collect()),
+            ),
+            _ => (None, None, None),
+        };
+
+        Node {
+            id: r.id(),
+            author: r.author(),
+            signature: r.signature(),
+            kind: r.content().kind(),
+            parents: r.content().parents(),
+            name,
+            nonce,
+            access,
+            meta,
+            readers,
+            body,
+            text,
+            digest: r.content().digest().map(|d| util::to_hex(d.as_ref())),
+            attachment: r.content().attachment(),
+        }
+    }
+}
+
+/// Output of `origin`, `append` and `join`: the node that was added.
+#[derive(Debug, Serialize)]
+pub struct Created<'a> {
+    pub node: Node<'a>,
+}
+
+/// Output of `show`. `verified` is null when the author isn't known.
+/// `identity` is the first key of the author's identity, which is the
+/// author itself unless its key has been rotated.
+#[derive(Debug, Serialize)]
+pub struct Shown<'a> {
+    pub node: Node<'a>,
+    pub identity: &'a AuthorId,
+    pub verified: Option<bool>,
+    pub revoked: bool,
+
+    /// The body of an Append on a private timeline, when asked for
+    /// with `--decrypt`.
+    pub decrypted: Option<Decrypted<'a>>,
+}
+
+/// A decrypted body, laid out like the body of a Node.
+#[derive(Debug, Serialize)]
+pub struct Decrypted<'a> {
+    pub body: String,
+    pub text: Option<&'a str>,
+}
+
+impl<'a> Decrypted<'a> {
+    pub fn new(plain: &'a [u8]) -> Decrypted<'a> {
+        Decrypted {
+            body: util::to_hex(plain),
+            text: ::std::str::from_utf8(plain).ok(),
+        }
+    }
+}
+
+/// Output of `merge-base`.
+#[derive(Debug, Serialize)]
+pub struct MergeBases<'a> {
+    pub left: &'a NodeId,
+    pub right: &'a NodeId,
+    pub merge_bases: &'a [NodeId],
+}
+
+/// Output of `is-ancestor`.
+#[derive(Debug, Serialize)]
+pub struct IsAncestor<'a> {
+    pub ancestor: &'a NodeId,
+    pub descendant: &'a NodeId,
+    pub is_ancestor: bool,
+}
+
+/// Output of `range`.
+#[derive(Debug, Serialize)]
+pub struct Range<'a> {
+    pub from: &'a NodeId,
+    pub to: &'a NodeId,
+    pub nodes: &'a [NodeId],
+}
+
+/// Output of `log`. Only the lines holding nodes are included, each
+/// with its graph column.
+#[derive(Debug, Serialize)]
+pub struct Log<'a> {
+    pub start: &'a NodeId,
+    pub nodes: Vec<LogNode<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogNode<'a> {
+    pub graph: &'a str,
+    pub node: Node<'a>,
+}
+
+/// Output of `import`: the counts from `bundle::Imported` along with
+/// the imported nodes a revocation makes suspect.
+#[derive(Debug, Serialize)]
+pub struct Imported<'a> {
+    #[serde(flatten)]
+    pub counts: &'a bundle::Imported,
+    pub revoked: Vec<&'a NodeId>,
+
+    /// How many imported records had bodies already redacted here.
+    pub redacted: usize,
+
+    /// How many of the bundle's authors were added to the registry,
+    /// which only happens with `--remember-authors`.
+    pub remembered: usize,
+}
+
+/// Output of `export`.
+#[derive(Debug, Serialize)]
+pub struct Exported<'a> {
+    pub output: &'a str,
+    pub authors: usize,
+    pub remnants: usize,
+    pub chunks: usize,
+}
+
+/// Output of `redact`: the signed redaction, and whether it was
+/// applied or only recorded until the record arrives.
+#[derive(Debug, Serialize)]
+pub struct Redacted<'a> {
+    pub redaction: &'a Redaction,
+    pub applied: bool,
+}
+
+/// Output of `compress`: how many stored items were rewritten.
+#[derive(Debug, Serialize)]
+pub struct Compressed {
+    pub rewritten: usize,
+}
+
+/// An author in the keyring. Output of the `author` subcommands that
+/// change one.
+#[derive(Debug, Serialize)]
+pub struct AuthorEntry<'a> {
+    pub label: &'a str,
+    pub author: &'a AuthorId,
+    pub encrypted: bool,
+    pub default: bool,
+}
+
+impl<'a> AuthorEntry<'a> {
+    pub fn new(e: &'a keyring::Entry) -> AuthorEntry<'a> {
+        AuthorEntry {
+            label: &e.label,
+            author: e.author.id(),
+            encrypted: e.sealed,
+            default: e.default,
+        }
+    }
+}
+
+/// Output of `author rotate`: the keyring entry now holding the new
+/// key, and the label the old key was moved to.
+#[derive(Debug, Serialize)]
+pub struct Rotated<'a> {
+    pub author: AuthorEntry<'a>,
+    pub retired: &'a str,
+    pub identity: &'a AuthorId,
+}
+
+/// Output of `author mnemonic`.
+#[derive(Debug, Serialize)]
+pub struct Mnemonic<'a> {
+    pub author: &'a AuthorId,
+    pub mnemonic: &'a str,
+}
+
+/// An author in the registry. Output of the `trust` subcommands that
+/// change one.
+#[derive(Debug, Serialize)]
+pub struct TrustEntry<'a> {
+    pub author: &'a AuthorId,
+    pub trust: registry::Trust,
+    pub note: Option<&'a str>,
+}
+
+impl<'a> TrustEntry<'a> {
+    pub fn new(e: &'a registry::Entry) -> TrustEntry<'a> {
+        TrustEntry {
+            author: e.author.id(),
+            trust: e.trust,
+            note: e.note.as_deref(),
+        }
+    }
+}
+
+/// Output of `trust remove`.
+#[derive(Debug, Serialize)]
+pub struct Removed<'a> {
+    pub author: &'a AuthorId,
+}
+
+/// Output of `trust list` and `trust policy`.
+#[derive(Debug, Serialize)]
+pub struct Registry<'a> {
+    pub policy: registry::Policy,
+    pub authors: Vec<TrustEntry<'a>>,
+}
+
+/// Output of `author list`.
+#[derive(Debug, Serialize)]
+pub struct Authors<'a> {
+    pub authors: Vec<AuthorEntry<'a>>,
+}
+
+/// Output of any command that failed.
+#[derive(Debug, Serialize)]
+pub struct Error {
+    pub error: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+    use serde_json;
+    use sodiumoxide::crypto::hash::sha256 as hash;
+
+    #[test]
+    fn nodes_have_a_stable_shape() {
+        let a = Author::new();
+        let o = Remnant::origin(&a, "doc");
+        let t = o.append(&a, b"hi");
+
+        let json = serde_json::to_value(Node::new(&t)).unwrap();
+        assert_eq!(
+            json!({
+                "id": util::to_hex(t.id().bytes()),
+                "author": util::to_hex(a.id().bytes()),
+                "signature": util::to_hex(t.signature().bytes()),
+                "kind": "append",
+                "parents": [util::to_hex(o.id().bytes())],
+                "name": null,
+                "nonce": null,
+                "access": null,
+                "meta": null,
+                "readers": null,
+                "body": "6869",
+                "text": "hi",
+                "digest": util::to_hex(hash::hash(b"hi").as_ref()),
+                "attachment": null,
+            }),
+            json
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use author::{Author, AuthorId, PartialAuthor};
+use serde_json;
+use sodiumoxide::crypto::pwhash::argon2id13 as pwhash;
+use sodiumoxide::crypto::secretbox;
+use sodiumoxide::crypto::sign;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// The key derivation function recorded in sealed files.
+const KDF: &str = "argon2id13";
+
+/// What an author file holds. Plain files keep the secret key in the
+/// clear, which is all older versions could write. Sealed files keep
+/// it encrypted under a passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AuthorFile {
+    Sealed(SealedAuthor),
+    Plain(Author),
+}
+
+/// An Author whose secret key is encrypted with a key derived from a
+/// passphrase. The public key and id stay readable so the author can
+/// be identified without the passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedAuthor {
+    pk: sign::PublicKey,
+    id: AuthorId,
+
+    kdf: String,
+    opslimit: usize,
+    memlimit: usize,
+    salt: pwhash::Salt,
+
+    nonce: secretbox::Nonce,
+    sealed_sk: Vec<u8>,
+}
+
+impl SealedAuthor {
+    /// Encrypt the author's secret key under `passphrase`.
+    pub fn seal(author: &Author, passphrase: &[u8]) -> io::Result<SealedAuthor> {
+        let salt = pwhash::gen_salt();
+        let opslimit = pwhash::OPSLIMIT_INTERACTIVE;
+        let memlimit = pwhash::MEMLIMIT_INTERACTIVE;
+        let key = derive_key(passphrase, &salt, opslimit.0, memlimit.0)?;
+        let nonce = secretbox::gen_nonce();
+
+        let sign::SecretKey(ref sk) = *author.sk();
+
+        Ok(SealedAuthor {
+            pk: *author.pk(),
+            id: author.id().clone(),
+            kdf: KDF.to_string(),
+            opslimit: opslimit.0,
+            memlimit: memlimit.0,
+            salt,
+            sealed_sk: secretbox::seal(sk, &nonce, &key),
+            nonce,
+        })
+    }
+
+    /// Decrypt the secret key. Fails if the passphrase is wrong or the
+    /// file has been tampered with.
+    pub fn unseal(&self, passphrase: &[u8]) -> io::Result<Author> {
+        if self.kdf != KDF {
+            return err(&format!("unsupported key derivation: {}", self.kdf));
+        }
+
+        let key = derive_key(passphrase, &self.salt, self.opslimit, self.memlimit)?;
+        let sk = secretbox::open(&self.sealed_sk, &self.nonce, &key)
+            .map_err(|_| io::Error::new(io::ErrorKind::PermissionDenied, "wrong passphrase"))?;
+        let sk = sign::SecretKey::from_slice(&sk).map_or_else(|| err("bad secret key"), Ok)?;
+
+        let author = Author::from_sk(sk);
+        if *author.id() != self.id {
+            return err("secret key does not match the author id");
+        }
+
+        Ok(author)
+    }
+
+    pub fn partial(&self) -> PartialAuthor {
+        PartialAuthor::from_pk(self.pk)
+    }
+}
+
+impl AuthorFile {
+    /// The public half of the author, available without a passphrase.
+    pub fn partial(&self) -> PartialAuthor {
+        match self {
+            AuthorFile::Sealed(s) => s.partial(),
+            AuthorFile::Plain(a) => a.partial(),
+        }
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        match self {
+            AuthorFile::Sealed(_) => true,
+            AuthorFile::Plain(_) => false,
+        }
+    }
+
+    /// Get the full Author. `passphrase` is only called if the file is
+    /// sealed.
+    pub fn unlock<F>(&self, passphrase: F) -> io::Result<Author>
+    where
+        F: FnOnce() -> io::Result<String>,
+    {
+        match self {
+            AuthorFile::Sealed(s) => s.unseal(passphrase()?.as_bytes()),
+            AuthorFile::Plain(a) => Ok(a.clone()),
+        }
+    }
+}
+
+/// Read an author file.
+pub fn load(path: &Path) -> io::Result<AuthorFile> {
+    let mut f = fs::File::open(path)?;
+    let mut s = String::new();
+    f.read_to_string(&mut s)?;
+    Ok(serde_json::from_str(&s)?)
+}
+
+/// Write an author file, replacing any that's already there.
+pub fn save(path: &Path, file: &AuthorFile) -> io::Result<()> {
+    save_private(path, &serde_json::to_string(file)?)
+}
+
+/// Write a file only its owner can read, replacing any that's already
+/// there.
+pub fn save_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+
+    let mut f = opts.open(path)?;
+    f.write_all(contents.as_bytes())
+}
+
+fn derive_key(
+    passphrase: &[u8],
+    salt: &pwhash::Salt,
+    opslimit: usize,
+    memlimit: usize,
+) -> io::Result<secretbox::Key> {
+    let mut key = secretbox::Key([0; secretbox::KEYBYTES]);
+    {
+        let secretbox::Key(ref mut kb) = key;
+        pwhash::derive_key(
+            kb,
+            passphrase,
+            salt,
+            pwhash::OpsLimit(opslimit),
+            pwhash::MemLimit(memlimit),
+        )
+        .map_err(|_| io::Error::other("key derivation failed"))?;
+    }
+    Ok(key)
+}
+
+fn err<T>(msg: &str) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir;
+
+    #[test]
+    fn sealed_files_need_the_passphrase() {
+        let tdir = tempdir::TempDir::new("keyfile_test").unwrap();
+        let path = tdir.path().join("author.json");
+        let a = Author::new();
+
+        save(
+            &path,
+            &AuthorFile::Sealed(SealedAuthor::seal(&a, b"hunter2").unwrap()),
+        )
+        .unwrap();
+
+        let file = load(&path).unwrap();
+        assert!(file.is_sealed());
+        assert_eq!(a.partial(), file.partial());
+        assert_eq!(a, file.unlock(|| Ok("hunter2".to_string())).unwrap());
+        assert!(file.unlock(|| Ok("hunter3".to_string())).is_err());
+
+        let mut json = String::new();
+        fs::File::open(&path)
+            .unwrap()
+            .read_to_string(&mut json)
+            .unwrap();
+        assert!(!json.contains("\"sk\""));
+    }
+
+    #[test]
+    fn plain_files_still_load() {
+        let tdir = tempdir::TempDir::new("keyfile_test").unwrap();
+        let path = tdir.path().join("author.json");
+        let a = Author::new();
+
+        // This is how author files were written before they could be
+        // sealed.
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(serde_json::to_string(&a).unwrap().as_bytes())
+            .unwrap();
+
+        let file = load(&path).unwrap();
+        assert!(!file.is_sealed());
+        assert_eq!(a, file.unlock(|| panic!("no passphrase needed")).unwrap());
+    }
+}
@@ -0,0 +1,206 @@
+use author::PartialAuthor;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json;
+use sodiumoxide::crypto::sign;
+use std::io;
+
+/// The key type of Ed25519 keys in OpenSSH.
+const SSH_ED25519: &str = "ssh-ed25519";
+
+/// The DER encoding of an Ed25519 SubjectPublicKeyInfo up to the key
+/// itself (RFC 8410): a sequence holding the algorithm identifier
+/// 1.3.101.112 and a bit string of 32 bytes.
+const SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+const PEM_BEGIN: &str = "-----BEGIN PUBLIC KEY-----";
+const PEM_END: &str = "-----END PUBLIC KEY-----";
+
+/// The ways a public key can be written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// A serialized `PartialAuthor`, as bundles hold them.
+    Json,
+
+    /// An `ssh-ed25519` line, as in `authorized_keys`.
+    OpenSsh,
+
+    /// A PEM encoded PKCS#8 SubjectPublicKeyInfo, as written by
+    /// `openssl pkey -pubout`.
+    Pem,
+}
+
+impl KeyFormat {
+    pub fn from_name(name: &str) -> Option<KeyFormat> {
+        match name {
+            "json" => Some(KeyFormat::Json),
+            "openssh" => Some(KeyFormat::OpenSsh),
+            "pem" => Some(KeyFormat::Pem),
+            _ => None,
+        }
+    }
+}
+
+/// Write the author's public key in `format`. `comment` ends OpenSSH
+/// lines and is ignored otherwise.
+pub fn write(author: &PartialAuthor, format: KeyFormat, comment: &str) -> String {
+    match format {
+        KeyFormat::Json => serde_json::to_string(author).unwrap(),
+        KeyFormat::OpenSsh => to_openssh(author, comment),
+        KeyFormat::Pem => to_pem(author),
+    }
+}
+
+/// Read a public key in any of the formats, working out which from
+/// the text.
+pub fn read(text: &str) -> io::Result<PartialAuthor> {
+    let t = text.trim();
+
+    if t.starts_with(PEM_BEGIN) {
+        from_pem(t)
+    } else if t.starts_with('{') {
+        let a: PartialAuthor = serde_json::from_str(t)?;
+        if a.check() {
+            Ok(a)
+        } else {
+            Err(invalid("the author id does not match the public key"))
+        }
+    } else {
+        from_openssh(t)
+    }
+}
+
+pub fn to_openssh(author: &PartialAuthor, comment: &str) -> String {
+    let sign::PublicKey(ref pk) = *author.pk();
+
+    let mut blob = Vec::new();
+    put_string(&mut blob, SSH_ED25519.as_bytes());
+    put_string(&mut blob, pk);
+
+    let line = format!("{} {}", SSH_ED25519, STANDARD.encode(&blob));
+    if comment.is_empty() {
+        line
+    } else {
+        format!("{} {}", line, comment)
+    }
+}
+
+/// Read the first `ssh-ed25519` key on the line. Anything before it,
+/// like `authorized_keys` options, and the comment after it are
+/// ignored.
+pub fn from_openssh(line: &str) -> io::Result<PartialAuthor> {
+    let mut fields = line.split_whitespace().skip_while(|f| *f != SSH_ED25519);
+    let encoded = match (fields.next(), fields.next()) {
+        (Some(_), Some(e)) => e,
+        _ => return Err(invalid("not an ssh-ed25519 public key")),
+    };
+
+    let blob = STANDARD
+        .decode(encoded)
+        .map_err(|_| invalid("bad base64 in ssh key"))?;
+
+    let mut rest = &blob[..];
+    let kind = take_string(&mut rest)?;
+    let pk = take_string(&mut rest)?;
+    if kind != SSH_ED25519.as_bytes() || !rest.is_empty() {
+        return Err(invalid("not an ssh-ed25519 public key"));
+    }
+
+    public_key(pk)
+}
+
+pub fn to_pem(author: &PartialAuthor) -> String {
+    let sign::PublicKey(ref pk) = *author.pk();
+
+    let mut der = SPKI_PREFIX.to_vec();
+    der.extend(pk);
+
+    format!("{}\n{}\n{}\n", PEM_BEGIN, STANDARD.encode(&der), PEM_END)
+}
+
+pub fn from_pem(pem: &str) -> io::Result<PartialAuthor> {
+    let body: String = pem
+        .lines()
+        .map(|l| l.trim())
+        .skip_while(|l| *l != PEM_BEGIN)
+        .skip(1)
+        .take_while(|l| *l != PEM_END)
+        .collect();
+
+    let der = STANDARD
+        .decode(&body)
+        .map_err(|_| invalid("bad base64 in PEM key"))?;
+
+    if der.len() != SPKI_PREFIX.len() + sign::PUBLICKEYBYTES
+        || der[..SPKI_PREFIX.len()] != SPKI_PREFIX
+    {
+        return Err(invalid("not an Ed25519 public key"));
+    }
+
+    public_key(&der[SPKI_PREFIX.len()..])
+}
+
+fn public_key(bytes: &[u8]) -> io::Result<PartialAuthor> {
+    sign::PublicKey::from_slice(bytes)
+        .map(PartialAuthor::from_pk)
+        .ok_or_else(|| invalid("public key is the wrong length"))
+}
+
+/// Append an SSH wire format string: a big endian length and the bytes.
+fn put_string(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.extend(&(s.len() as u32).to_be_bytes());
+    buf.extend(s);
+}
+
+fn take_string<'a>(buf: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    if buf.len() < 4 {
+        return Err(invalid("truncated ssh key"));
+    }
+    let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    if buf.len() < 4 + len {
+        return Err(invalid("truncated ssh key"));
+    }
+
+    let s = &buf[4..4 + len];
+    *buf = &buf[4 + len..];
+    Ok(s)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+
+    #[test]
+    fn round_trip_every_format() {
+        let a = Author::new().partial();
+
+        for f in &[KeyFormat::Json, KeyFormat::OpenSsh, KeyFormat::Pem] {
+            assert_eq!(a, read(&write(&a, *f, "ops@example")).unwrap());
+        }
+    }
+
+    #[test]
+    fn reads_keys_made_by_other_tools() {
+        // The RFC 8032 test key 1, as written by ssh-keygen and openssl.
+        let pk = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+        let ssh = "restrict ssh-ed25519 \
+                   AAAAC3NzaC1lZDI1NTE5AAAAINdamAGCsQq31Uv+08lkBzoO4XLz2qYjJa8CGmj3B1Ea \
+                   test@host";
+        let pem = "-----BEGIN PUBLIC KEY-----\n\
+                   MCowBQYDK2VwAyEA11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=\n\
+                   -----END PUBLIC KEY-----\n";
+
+        let from_ssh = from_openssh(ssh).unwrap();
+        assert_eq!(pk, ::util::to_hex(&from_ssh.pk()[..]));
+        assert_eq!(from_ssh, from_pem(pem).unwrap());
+
+        assert!(read("ssh-rsa AAAAB3NzaC1yc2E= nope").is_err());
+    }
+}
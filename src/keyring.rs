@@ -0,0 +1,306 @@
+use author::{Author, PartialAuthor};
+use keyfile;
+use keyfile::{AuthorFile, SealedAuthor};
+use revocation::Revocation;
+use serde_json;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use util;
+
+/// The file naming the default author.
+const DEFAULT_FILE: &str = "default";
+
+/// The extension of author files.
+const EXTENSION: &str = "json";
+
+/// The extension of the revocation certificates made alongside
+/// author files.
+const REVOCATION_EXTENSION: &str = "rev";
+
+/// How many hex digits of its id a retired author's label gets.
+const RETIRED_ID_LEN: usize = 8;
+
+/// A directory of author files, each stored under a label. One of the
+/// authors can be marked as the default.
+#[derive(Debug)]
+pub struct Keyring {
+    dir: PathBuf,
+}
+
+/// An author in the keyring, as listed by `entries`.
+#[derive(Debug)]
+pub struct Entry {
+    pub label: String,
+    pub author: PartialAuthor,
+    pub sealed: bool,
+    pub default: bool,
+}
+
+impl Keyring {
+    /// Open the keyring in `dir`, creating the directory if needed.
+    pub fn open(dir: &Path) -> io::Result<Keyring> {
+        fs::create_dir_all(dir)?;
+        Ok(Keyring {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Every label in the keyring, sorted.
+    pub fn labels(&self) -> io::Result<Vec<String>> {
+        let mut labels = Vec::new();
+        for e in fs::read_dir(&self.dir)? {
+            let p = e?.path();
+            if p.extension().and_then(|x| x.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            if let Some(l) = p.file_stem().and_then(|s| s.to_str()) {
+                labels.push(l.to_string());
+            }
+        }
+        labels.sort();
+        Ok(labels)
+    }
+
+    /// Every author in the keyring, sorted by label.
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        let default = self.default()?;
+        let mut entries = Vec::new();
+        for label in self.labels()? {
+            let file = self.load(&label)?;
+            entries.push(Entry {
+                default: default.as_ref() == Some(&label),
+                author: file.partial(),
+                sealed: file.is_sealed(),
+                label,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// The public halves of every author in the keyring.
+    pub fn partials(&self) -> io::Result<Vec<PartialAuthor>> {
+        Ok(self.entries()?.into_iter().map(|e| e.author).collect())
+    }
+
+    pub fn contains(&self, label: &str) -> bool {
+        check_label(label).is_ok() && self.path(label).exists()
+    }
+
+    pub fn load(&self, label: &str) -> io::Result<AuthorFile> {
+        if !self.contains(label) {
+            return err(&format!("no author labelled {}", label));
+        }
+        keyfile::load(&self.path(label))
+    }
+
+    /// Replace the file of an author already in the keyring. The new
+    /// file must hold the same author.
+    pub fn save(&self, label: &str, file: &AuthorFile) -> io::Result<()> {
+        if self.load(label)?.partial() != file.partial() {
+            return err(&format!("{} holds a different author", label));
+        }
+        keyfile::save(&self.path(label), file)
+    }
+
+    /// Add an author under a new label. The first author added becomes
+    /// the default.
+    pub fn add(&self, label: &str, file: &AuthorFile) -> io::Result<()> {
+        check_label(label)?;
+        if self.contains(label) {
+            return err(&format!("an author labelled {} already exists", label));
+        }
+
+        keyfile::save(&self.path(label), file)?;
+
+        if self.default()?.is_none() {
+            self.set_default(label)?;
+        }
+        Ok(())
+    }
+
+    /// The label of the default author, if there is one.
+    pub fn default(&self) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.dir.join(DEFAULT_FILE)) {
+            Ok(l) => Ok(Some(l.trim().to_string()).filter(|l| self.contains(l))),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_default(&self, label: &str) -> io::Result<()> {
+        if !self.contains(label) {
+            return err(&format!("no author labelled {}", label));
+        }
+        fs::File::create(self.dir.join(DEFAULT_FILE))?.write_all(label.as_bytes())
+    }
+
+    /// Find the label of an author given either its label or a prefix
+    /// of its id in hex. Labels win over id prefixes.
+    pub fn find(&self, selector: &str) -> io::Result<String> {
+        if self.contains(selector) {
+            return Ok(selector.to_string());
+        }
+
+        let mut found = self
+            .entries()?
+            .into_iter()
+            .filter(|e| util::to_hex(e.author.id().bytes()).starts_with(selector));
+
+        match (found.next(), found.next()) {
+            (Some(e), None) => Ok(e.label),
+            (None, _) => err(&format!("no author matches {}", selector)),
+            (Some(_), Some(_)) => err(&format!("{} matches more than one author", selector)),
+        }
+    }
+
+    /// Encrypt the secret key of an author under `passphrase`.
+    pub fn seal(&self, label: &str, passphrase: &str) -> io::Result<()> {
+        match self.load(label)? {
+            AuthorFile::Plain(a) => self.save(
+                label,
+                &AuthorFile::Sealed(SealedAuthor::seal(&a, passphrase.as_bytes())?),
+            ),
+            AuthorFile::Sealed(_) => err(&format!("{} is already encrypted", label)),
+        }
+    }
+
+    /// Store the secret key of an author in the clear again.
+    pub fn unseal(&self, label: &str, passphrase: &str) -> io::Result<()> {
+        match self.load(label)? {
+            AuthorFile::Sealed(s) => {
+                self.save(label, &AuthorFile::Plain(s.unseal(passphrase.as_bytes())?))
+            }
+            AuthorFile::Plain(_) => err(&format!("{} is not encrypted", label)),
+        }
+    }
+
+    /// Replace the author under `label` with a new one, keeping the
+    /// old author under a label of its own. Returns that label. The
+    /// default stays with `label`.
+    pub fn retire(&self, label: &str, file: &AuthorFile) -> io::Result<String> {
+        let old = self.load(label)?;
+        let retired = format!(
+            "{}.{}",
+            label,
+            &util::to_hex(old.partial().id().bytes())[..RETIRED_ID_LEN]
+        );
+        if self.contains(&retired) {
+            return err(&format!("an author labelled {} already exists", retired));
+        }
+
+        fs::rename(self.path(label), self.path(&retired))?;
+        if self.revocation_path(label).exists() {
+            fs::rename(self.revocation_path(label), self.revocation_path(&retired))?;
+        }
+        keyfile::save(&self.path(label), file)?;
+        Ok(retired)
+    }
+
+    /// Keep a revocation certificate for the author under `label`, to
+    /// be published if its key is ever lost or compromised.
+    pub fn save_revocation(&self, label: &str, r: &Revocation) -> io::Result<()> {
+        if self.load(label)?.partial() != *r.author() {
+            return err(&format!("the revocation is not for {}", label));
+        }
+        keyfile::save_private(&self.revocation_path(label), &serde_json::to_string(r)?)
+    }
+
+    /// The revocation certificate kept for the author under `label`.
+    pub fn revocation(&self, label: &str) -> io::Result<Revocation> {
+        let p = self.revocation_path(label);
+        if !p.exists() {
+            return err(&format!("no revocation certificate for {}", label));
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(p)?)?)
+    }
+
+    /// Unlock an author, asking for the passphrase only if its secret
+    /// key is sealed.
+    pub fn unlock<F>(&self, label: &str, passphrase: F) -> io::Result<Author>
+    where
+        F: FnOnce() -> io::Result<String>,
+    {
+        self.load(label)?.unlock(passphrase)
+    }
+
+    fn path(&self, label: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", label, EXTENSION))
+    }
+
+    fn revocation_path(&self, label: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", label, REVOCATION_EXTENSION))
+    }
+}
+
+/// Labels become file names, so keep them to a safe set of characters.
+fn check_label(label: &str) -> io::Result<()> {
+    let ok = !label.is_empty()
+        && !label.starts_with('.')
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+    if ok {
+        Ok(())
+    } else {
+        err(&format!("bad label: {}", label))
+    }
+}
+
+fn err<T>(msg: &str) -> io::Result<T> {
+    Err(io::Error::other(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir;
+
+    #[test]
+    fn finds_authors_by_label_or_id() {
+        let tdir = tempdir::TempDir::new("keyring_test").unwrap();
+        let k = Keyring::open(tdir.path()).unwrap();
+        let a = Author::new();
+        let b = Author::new();
+
+        k.add("alice", &AuthorFile::Plain(a.clone())).unwrap();
+        k.add("build-bot", &AuthorFile::Plain(b.clone())).unwrap();
+        assert!(k.add("alice", &AuthorFile::Plain(b.clone())).is_err());
+        assert!(k.add("../escape", &AuthorFile::Plain(b.clone())).is_err());
+
+        assert_eq!(vec!["alice", "build-bot"], k.labels().unwrap());
+        assert_eq!(Some("alice".to_string()), k.default().unwrap());
+        assert_eq!("alice", k.find("alice").unwrap());
+
+        let prefix = util::to_hex(b.id().bytes());
+        assert_eq!("build-bot", k.find(&prefix[..8]).unwrap());
+
+        k.set_default("build-bot").unwrap();
+        assert_eq!(Some("build-bot".to_string()), k.default().unwrap());
+        assert!(k.set_default("carol").is_err());
+    }
+
+    #[test]
+    fn seal_and_unseal_in_place() {
+        let tdir = tempdir::TempDir::new("keyring_test").unwrap();
+        let k = Keyring::open(tdir.path()).unwrap();
+        let a = Author::new();
+
+        k.add("alice", &AuthorFile::Plain(a.clone())).unwrap();
+        k.seal("alice", "hunter2").unwrap();
+        assert!(k.entries().unwrap()[0].sealed);
+        assert!(k.seal("alice", "hunter2").is_err());
+        assert!(k.unlock("alice", || Ok("nope".to_string())).is_err());
+
+        k.unseal("alice", "hunter2").unwrap();
+        assert_eq!(a, k.unlock("alice", || panic!("not sealed")).unwrap());
+
+        let b = Author::new();
+        let retired = k.retire("alice", &AuthorFile::Plain(b.clone())).unwrap();
+        assert_eq!(b, k.unlock("alice", || panic!("not sealed")).unwrap());
+        assert_eq!(a, k.unlock(&retired, || panic!("not sealed")).unwrap());
+        assert_eq!(Some("alice".to_string()), k.default().unwrap());
+    }
+}
@@ -1,40 +1,119 @@
+#![allow(clippy::new_without_default, clippy::result_large_err)]
+
 #[macro_use]
 extern crate serde_derive;
 
+extern crate base64;
 extern crate bincode;
+extern crate bip39;
+extern crate ciborium;
+extern crate rayon;
 extern crate serde;
+#[cfg_attr(test, macro_use)]
 extern crate serde_json;
 
 extern crate tempdir;
 
 extern crate clap;
+extern crate crc32fast;
+extern crate flate2;
+extern crate fs2;
+extern crate lru;
 extern crate sodiumoxide;
 
-///! A remnant is the primary representation of items in the Remnant
-///! system. It's an identifier, an author identifier, some content,
-///! and a signature.
+/// A remnant is the primary representation of items in the Remnant
+/// system. It's an identifier, an author identifier, some content,
+/// and a signature.
 pub mod remnant;
 
-///! An author is what creates Remnants. They consist of a public and
-///! private key pair along with an identifier which is the hash of
-///! the public key.
+/// An author is what creates Remnants. They consist of a public and
+/// private key pair along with an identifier which is the hash of
+/// the public key.
 pub mod author;
 
-///! A universe is a collection of Remnants. It supports methods for
-///! inserting more Remnants, querying existing Remnants, and backing
-///! the whole thing on disk.
+/// A universe is a collection of Remnants. It supports methods for
+/// inserting more Remnants, querying existing Remnants, and backing
+/// the whole thing on disk.
 pub mod universe;
 
-///! A protocol is the set of messages we send and recieve from peers
-///! in the network. These are typically meta messages about the
-///! authors and universes on each node in the network.
+/// A protocol is the set of messages we send and recieve from peers
+/// in the network. These are typically meta messages about the
+/// authors and universes on each node in the network.
 pub mod protocol;
 
 mod util;
 
-///! An execution plan for the command line client.
+/// An execution plan for the command line client.
 pub mod plan;
 
-///! Triefort is an on-disk trie that stores objects by hash in a
-///! directory structure using tries.
+/// Ancestry queries over the Remnants stored in a triefort: common
+/// ancestors of two nodes, ancestor checks, and range queries.
+pub mod ancestry;
+
+/// Lays out timelines for `remnant log`, with a graph column for
+/// branches and joins.
+pub mod log;
+
+/// Formats a single Remnant in full for `remnant show`.
+pub mod show;
+
+/// The documents printed by the command line client when it is
+/// asked for JSON output.
+pub mod json;
+
+/// Bundles are single files holding timelines and the public keys
+/// needed to verify them, for moving history between databases.
+pub mod bundle;
+
+/// Verifying many Remnants at once, in parallel, for imports and
+/// database checks.
+pub mod verify;
+
+/// Successions link a retired author key to the key replacing it, so
+/// history signed by either belongs to one identity.
+pub mod succession;
+
+/// Revocations declare an author key compromised, leaving only the
+/// history before a last good node trusted.
+pub mod revocation;
+
+/// Redactions are signed requests to remove the body of an Append
+/// while keeping its place in the timeline.
+pub mod redaction;
+
+/// Who may add to a timeline: the policy an origin declares and the
+/// membership changes that follow it.
+pub mod access;
+
+/// Recovery phrases: an author's seed written as words.
+pub mod mnemonic;
+
+/// Public keys in the formats other tools use: OpenSSH lines and PEM
+/// encoded PKCS#8.
+pub mod keyformat;
+
+/// The authors we trust, know or block, and the policy deciding
+/// whose records the database accepts.
+pub mod registry;
+
+/// Codecs for storing typed values in bodies: JSON, bincode and
+/// CBOR.
+pub mod codec;
+
+/// Private timelines, whose bodies only their readers can decrypt.
+pub mod private;
+
+/// Attachments: large bodies split into chunks stored by hash,
+/// which an Append refers to by the hash of their manifest.
+pub mod attachment;
+
+/// Author files on disk, with the secret key optionally encrypted
+/// under a passphrase.
+pub mod keyfile;
+
+/// A directory of labelled authors, one of which is the default.
+pub mod keyring;
+
+/// Triefort is an on-disk trie that stores objects by hash in a
+/// directory structure using tries.
 pub mod triefort;
@@ -0,0 +1,331 @@
+use remnant::{Content, NodeId, Remnant};
+use std::collections::HashSet;
+use std::io;
+use triefort;
+use util;
+
+/// The longest body preview shown on a log line, in characters.
+const PREVIEW_CHARS: usize = 50;
+
+/// Filters applied by `log`.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Stop after this many nodes have been shown.
+    pub limit: Option<usize>,
+
+    /// Only show nodes whose author id starts with this hex prefix.
+    pub author: Option<String>,
+
+    /// Hide this node and everything reachable from it.
+    pub since: Option<NodeId>,
+}
+
+/// One line of log output. Lines that only draw graph edges have no
+/// node.
+#[derive(Debug)]
+pub struct Line {
+    pub graph: String,
+    pub node: Option<Remnant>,
+}
+
+/// Walk a timeline and lay it out as a graph, children first. If
+/// `start` is an Origin the whole timeline growing from it is shown,
+/// otherwise `start` is treated as a head and its history is shown.
+pub fn log(
+    db: &triefort::Handle<Remnant>,
+    start: &NodeId,
+    opts: &Options,
+) -> io::Result<Vec<Line>> {
+    let nodes = match db.get(start.bytes())?.content() {
+        Content::Origin { .. } => db.descendants(start)?,
+        _ => db.ancestors(start)?,
+    };
+
+    let hidden: HashSet<NodeId> = match opts.since {
+        Some(ref s) => db.ancestors(s)?.into_iter().collect(),
+        None => HashSet::new(),
+    };
+
+    let nodes: Vec<NodeId> = nodes.into_iter().filter(|i| !hidden.contains(i)).collect();
+    let members: HashSet<&NodeId> = nodes.iter().collect();
+
+    let mut graph = Graph::new();
+    let mut lines = Vec::new();
+    let mut shown = 0;
+
+    for i in db.topological(&nodes)? {
+        if opts.limit.is_some_and(|l| shown >= l) {
+            break;
+        }
+
+        let r = db.get(i.bytes())?;
+        let parents: Vec<NodeId> = r
+            .content()
+            .parents()
+            .into_iter()
+            .filter(|p| members.contains(p))
+            .cloned()
+            .collect();
+
+        let show = match opts.author {
+            Some(ref a) => util::to_hex(r.author().bytes()).starts_with(&a.to_lowercase()),
+            None => true,
+        };
+
+        // Edges around hidden nodes would lead nowhere, so an author
+        // filter only keeps the rows of the nodes it shows.
+        let (mut before, row, mut after) = graph.node(&i, &parents);
+        if opts.author.is_some() {
+            before.clear();
+            after.clear();
+        }
+
+        lines.extend(before.into_iter().map(edge));
+        if show {
+            shown += 1;
+            lines.push(Line {
+                graph: row,
+                node: Some(r),
+            });
+        }
+        lines.extend(after.into_iter().map(edge));
+    }
+
+    Ok(lines)
+}
+
+fn edge(graph: String) -> Line {
+    Line { graph, node: None }
+}
+
+/// A one line description of a node: its short id, author, content
+/// kind, and a preview of what it holds.
+pub fn summary(r: &Remnant) -> String {
+    let preview = match r.content() {
+        Content::Origin { name: n, .. } => preview(n.as_bytes()),
+        Content::Append { body: b, .. } => preview(b.bytes()),
+        Content::Join { left: l, right: r } => format!("{} {}", l, r),
+        Content::Membership { access: a, .. } => a.to_string(),
+        Content::Attachment { attachment: a, .. } => format!("<{} byte attachment>", a.size),
+        Content::Redacted { .. } => "(body removed)".to_string(),
+    };
+
+    format!(
+        "{} {} {} {}",
+        r.id(),
+        r.author(),
+        r.content().kind(),
+        preview
+    )
+}
+
+/// The first line of `bytes` if it's UTF-8, shortened to fit on a
+/// log line. Anything else is described by its length.
+fn preview(bytes: &[u8]) -> String {
+    match ::std::str::from_utf8(bytes) {
+        Ok(s) => {
+            let first = s.lines().next().unwrap_or("");
+            let mut p: String = first.chars().take(PREVIEW_CHARS).collect();
+            if p.len() < s.len() {
+                p.push_str("...");
+            }
+            p
+        }
+        Err(_) => format!("<{} bytes>", bytes.len()),
+    }
+}
+
+/// Draws the graph column of a log, like `git log --graph`. Each lane
+/// is a column waiting for the node it will show next.
+#[derive(Debug, Default)]
+pub struct Graph {
+    lanes: Vec<NodeId>,
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph { lanes: Vec::new() }
+    }
+
+    /// Lay out `id`, whose parents are `parents`. Nodes must be given
+    /// children first. Returns the edge lines drawn before the node,
+    /// the node's own row, and the edge lines drawn after it.
+    pub fn node(&mut self, id: &NodeId, parents: &[NodeId]) -> (Vec<String>, String, Vec<String>) {
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+
+        let mut waiting: Vec<usize> = (0..self.lanes.len())
+            .filter(|&l| self.lanes[l] == *id)
+            .collect();
+
+        if waiting.is_empty() {
+            self.lanes.push(id.clone());
+            waiting.push(self.lanes.len() - 1);
+        }
+
+        // Several children lead to this node. Fold their lanes into
+        // the leftmost one.
+        let col = waiting[0];
+        let merged = &waiting[1..];
+        if !merged.is_empty() {
+            before.push(self.edges(|l| {
+                if merged.contains(&l) || l > merged[0] {
+                    Edge::Left
+                } else {
+                    Edge::Straight
+                }
+            }));
+            for &l in merged.iter().rev() {
+                self.lanes.remove(l);
+            }
+        }
+
+        let row = self.edges(|l| if l == col { Edge::Node } else { Edge::Straight });
+
+        match parents.split_first() {
+            None => {
+                if col + 1 < self.lanes.len() {
+                    after.push(self.edges(|l| if l > col { Edge::Left } else { Edge::Straight }));
+                }
+                self.lanes.remove(col);
+            }
+            Some((first, rest)) => {
+                self.lanes[col] = first.clone();
+                for (n, p) in rest.iter().enumerate() {
+                    after.push(self.edges(|l| {
+                        if l > col + n {
+                            Edge::Right
+                        } else if l == col + n {
+                            Edge::Fork
+                        } else {
+                            Edge::Straight
+                        }
+                    }));
+                    self.lanes.insert(col + n + 1, p.clone());
+                }
+            }
+        }
+
+        (before, row, after)
+    }
+
+    fn edges<F: Fn(usize) -> Edge>(&self, edge: F) -> String {
+        let mut s = vec![' '; self.lanes.len() * 2 + 1];
+
+        for l in 0..self.lanes.len() {
+            match edge(l) {
+                Edge::Straight => s[l * 2 + 1] = '|',
+                Edge::Node => s[l * 2 + 1] = '*',
+                Edge::Left => s[l * 2] = '/',
+                Edge::Right => s[l * 2 + 2] = '\\',
+                Edge::Fork => {
+                    s[l * 2 + 1] = '|';
+                    s[l * 2 + 2] = '\\';
+                }
+            }
+        }
+
+        s.into_iter()
+            .skip(1)
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+}
+
+enum Edge {
+    Straight,
+    Node,
+    Left,
+    Right,
+    Fork,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+    use tempdir;
+
+    fn render(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| match l.node {
+                Some(ref r) => format!("{} {}", l.graph, r.id()),
+                None => l.graph.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn graph_of_a_fork_and_join() {
+        let tdir = tempdir::TempDir::new("log_test").unwrap();
+        let hdl: triefort::Handle<Remnant> = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+        let a = Author::new();
+
+        let o = Remnant::origin(&a, "graph");
+        let l = o.append(&a, b"left");
+        let r = o.append(&a, b"right");
+        let j = Remnant::join(&a, &l, &r);
+
+        for n in &[&o, &l, &r, &j] {
+            hdl.insert(n).unwrap();
+        }
+
+        let lines = log(&hdl, j.id(), &Options::default()).unwrap();
+        assert_eq!(
+            vec![
+                format!("* {}", j.id()),
+                "|\\".to_string(),
+                format!("* | {}", l.id()),
+                format!("| * {}", r.id()),
+                "|/".to_string(),
+                format!("* {}", o.id()),
+            ],
+            render(&lines)
+        );
+
+        // Starting from the origin shows the same timeline.
+        assert_eq!(
+            render(&lines),
+            render(&log(&hdl, o.id(), &Options::default()).unwrap())
+        );
+
+        let opts = Options {
+            limit: Some(2),
+            since: Some(r.id().clone()),
+            ..Options::default()
+        };
+        let lines = log(&hdl, j.id(), &opts).unwrap();
+        assert_eq!(
+            vec![format!("* {}", j.id()), format!("* {}", l.id())],
+            render(&lines)
+        );
+
+        let opts = Options {
+            author: Some(util::to_hex(a.id().bytes())[..6].to_string()),
+            ..Options::default()
+        };
+        assert_eq!(
+            vec![
+                format!("* {}", j.id()),
+                format!("* | {}", l.id()),
+                format!("| * {}", r.id()),
+                format!("* {}", o.id()),
+            ],
+            render(&log(&hdl, j.id(), &opts).unwrap())
+        );
+    }
+
+    #[test]
+    fn summaries_preview_bodies() {
+        let a = Author::new();
+        let o = Remnant::origin(&a, "notes");
+        let t = o.append(&a, b"first line\nsecond line");
+        let b = o.append(&a, &[0xff, 0xfe, 0x00]);
+
+        assert!(summary(&o).ends_with(" origin notes"));
+        assert!(summary(&t).ends_with(" append first line..."));
+        assert!(summary(&b).ends_with(" append <3 bytes>"));
+    }
+}
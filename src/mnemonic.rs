@@ -0,0 +1,79 @@
+use bip39::Mnemonic;
+use sodiumoxide::crypto::sign;
+use std::io;
+
+/// The number of words in a recovery phrase.
+pub const WORDS: usize = 24;
+
+/// Write a seed as a recovery phrase of 24 words from the BIP39
+/// English word list. The seed is used as the BIP39 entropy directly,
+/// so the phrase carries the same 256 bits plus a checksum. It is not
+/// stretched into a wallet seed the way BIP39 wallets do.
+pub fn encode(seed: &sign::Seed) -> String {
+    let sign::Seed(ref bytes) = *seed;
+
+    // 32 bytes is always a valid amount of entropy.
+    Mnemonic::from_entropy(bytes).unwrap().to_string()
+}
+
+/// Read a recovery phrase written by `encode`. Case and the spacing
+/// between words don't matter, but every word and the checksum must
+/// be right.
+pub fn decode(phrase: &str) -> io::Result<sign::Seed> {
+    let words: Vec<String> = phrase
+        .split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect();
+
+    let m = Mnemonic::parse_normalized(&words.join(" "))
+        .map_err(|e| invalid(&format!("bad recovery phrase: {}", e)))?;
+
+    sign::Seed::from_slice(&m.to_entropy()).ok_or_else(|| {
+        invalid(&format!(
+            "recovery phrase has {} words, not {}",
+            words.len(),
+            WORDS
+        ))
+    })
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+
+    #[test]
+    fn phrases_restore_the_same_author() {
+        let a = Author::new();
+        let phrase = encode(&a.seed());
+        assert_eq!(WORDS, phrase.split(' ').count());
+
+        let b = Author::from_seed(&decode(&phrase).unwrap());
+        assert_eq!(a.id(), b.id());
+        assert_eq!(a, b);
+
+        let shouted = format!("  {}\n", phrase.to_uppercase().replace(' ', "   "));
+        assert_eq!(a.seed(), decode(&shouted).unwrap());
+    }
+
+    #[test]
+    fn bad_phrases_are_rejected() {
+        let phrase = encode(&sign::Seed([7; sign::SEEDBYTES]));
+        let mut words: Vec<&str> = phrase.split(' ').collect();
+
+        // Swapping two different words breaks the checksum.
+        let other = (1..words.len()).find(|&i| words[i] != words[0]).unwrap();
+        words.swap(0, other);
+        assert!(decode(&words.join(" ")).is_err());
+
+        // Twelve words is valid BIP39, but too short a seed.
+        let short = Mnemonic::from_entropy(&[7; 16]).unwrap().to_string();
+        assert!(decode(&short).is_err());
+
+        assert!(decode("not a recovery phrase").is_err());
+    }
+}
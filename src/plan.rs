@@ -1,31 +1,386 @@
 extern crate clap;
-use author::Author;
+use access::Access;
+use attachment::Chunk;
+use author::{Author, AuthorId, PartialAuthor};
 use clap::ArgMatches;
-use remnant::Remnant;
+use keyfile;
+use keyfile::{AuthorFile, SealedAuthor};
+use keyformat::KeyFormat;
+use keyring::Keyring;
+use redaction::Redaction;
+use registry::{Policy, Registry, Trust};
+use remnant::{Metadata, Remnant};
+use revocation::Revocation;
+use sodiumoxide::crypto::hash::sha256 as hash;
+use std::collections::HashSet;
+use std::env;
 use std::env::current_dir;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::process;
+use succession::Succession;
 use triefort;
+use triefort::{CacheLimit, Compression};
+use util;
 
-use serde_json;
+/// The largest body `append` accepts unless told otherwise.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
 
-#[derive(Debug)]
+/// How many decoded records the database keeps in memory unless told
+/// otherwise.
+pub const DEFAULT_CACHE_ENTRIES: usize = 4096;
+
+/// The label given to an author created without being asked for.
+pub const DEFAULT_LABEL: &str = "default";
+
+#[derive(Debug, Clone)]
 pub enum Command {
-    Append { parent: String, body: Vec<u8> },
-    Origin { name: String },
-    Join { left: String, right: String },
+    Append {
+        parent: String,
+        body: Vec<u8>,
+    },
+    Origin {
+        name: String,
+        allow: Allow,
+        deterministic: bool,
+        meta: Metadata,
+        encrypt: bool,
+    },
+    Join {
+        left: String,
+        right: String,
+    },
+    Attach {
+        parent: String,
+        file: String,
+    },
+    Attachment {
+        node: String,
+        output: Option<String>,
+    },
+    Membership {
+        parent: String,
+        allow: Allow,
+    },
+    Redact {
+        node: Option<String>,
+        reason: Option<String>,
+        request: Option<String>,
+    },
+    MergeBase {
+        left: String,
+        right: String,
+    },
+    IsAncestor {
+        ancestor: String,
+        descendant: String,
+    },
+    Range {
+        from: String,
+        to: String,
+    },
+    Show {
+        node: String,
+        decrypt: bool,
+    },
+    Export {
+        heads: Vec<String>,
+        output: String,
+    },
+    Import {
+        input: String,
+        reject_revoked: bool,
+
+        /// Keep the bundle's public keys in the registry as known
+        /// authors. Only done when asked, since the registry holds
+        /// trust decisions.
+        remember_authors: bool,
+    },
+    Fsck,
+    Compress {
+        method: Compression,
+    },
+    Log {
+        start: String,
+        limit: Option<usize>,
+        author: Option<String>,
+        since: Option<String>,
+    },
+    AuthorNew {
+        label: String,
+        default: bool,
+        encrypt: bool,
+    },
+    AuthorList,
+    AuthorDefault {
+        label: Option<String>,
+    },
+    AuthorExportPublic {
+        output: Option<String>,
+        key_format: KeyFormat,
+    },
+    AuthorConvertPublic {
+        input: String,
+        key_format: KeyFormat,
+    },
+    AuthorEncrypt,
+    AuthorDecrypt,
+    AuthorRotate,
+    AuthorMnemonic,
+    AuthorRestore {
+        label: String,
+        default: bool,
+        encrypt: bool,
+    },
+    AuthorRevoke {
+        last_good: Option<String>,
+        certificate: Option<String>,
+    },
+    AuthorRevocation {
+        output: Option<String>,
+    },
+    TrustAdd {
+        input: String,
+        trust: Trust,
+        note: Option<String>,
+    },
+    TrustSet {
+        author: String,
+        trust: Trust,
+    },
+    TrustRemove {
+        author: String,
+    },
+    TrustList,
+    TrustPolicy {
+        policy: Option<Policy>,
+    },
+}
+
+impl Command {
+    /// Whether the command needs the author's secret key, to sign a
+    /// new record or to decrypt one.
+    pub fn unlocks(&self) -> bool {
+        matches!(
+            self,
+            Command::Append { .. }
+                | Command::Origin { .. }
+                | Command::Join { .. }
+                | Command::Attach { .. }
+                | Command::Membership { .. }
+                | Command::Show { decrypt: true, .. }
+                | Command::Redact { request: None, .. }
+        )
+    }
+
+    /// Whether the command might change the database, and so needs it
+    /// to itself rather than sharing it with other readers.
+    pub fn writes(&self) -> bool {
+        !matches!(
+            self,
+            Command::Log { .. }
+                | Command::Show { decrypt: false, .. }
+                | Command::MergeBase { .. }
+                | Command::Range { .. }
+                | Command::IsAncestor { .. }
+                | Command::Fsck
+                | Command::Export { .. }
+                | Command::TrustList
+        )
+    }
+}
+
+/// Who may add to a timeline, as given on the command line. Members
+/// are labels or id prefixes until `Plan::access` looks them up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Allow {
+    Anyone,
+    Owner,
+    Members(Vec<String>),
+}
+
+/// How the command line client prints its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
 }
 
 #[derive(Debug)]
 pub struct Plan {
     pub validate: bool,
+    pub format: Format,
     pub path: String,
     pub command: Command,
+    pub passphrase: Passphrase,
+
+    /// The label or id prefix given with `--as`.
+    pub as_author: Option<String>,
+    pub keyring: Keyring,
 
-    pub author: Author,
+    /// The author we act as. The secret key is only unlocked for
+    /// commands that sign.
+    pub author: Option<Author>,
     pub database: triefort::Handle<Remnant>,
+
+    /// The chunks of attachments, kept apart from the records.
+    pub chunks: triefort::Handle<Chunk>,
+    pub successions: triefort::Handle<Succession>,
+    pub revocations: triefort::Handle<Revocation>,
+    pub redactions: triefort::Handle<Redaction>,
+    pub registry: Registry,
+
+    /// Held while the plan lives, so two processes don't write to the
+    /// same database at once.
+    pub lock: triefort::Lock,
+}
+
+impl Plan {
+    /// The label of the author we act as.
+    pub fn selected(&self) -> io::Result<String> {
+        select(&self.keyring, self.as_author.as_deref())
+    }
+
+    /// Get a passphrase to encrypt with, asking twice if we have to
+    /// ask at all.
+    pub fn new_passphrase(&self) -> io::Result<String> {
+        let p = self.passphrase.read("new passphrase: ")?;
+        if self.passphrase.is_interactive() && self.passphrase.read("again: ")? != p {
+            return err("passphrases do not match");
+        }
+        Ok(p)
+    }
+
+    /// Check the authors of Remnants about to be inserted against the
+    /// registry's policy. Trust follows key rotations, and authors in
+    /// the keyring are trusted unless the registry says otherwise.
+    pub fn admit(&self, remnants: &[&Remnant]) -> io::Result<()> {
+        let policy = self.registry.policy();
+        let ours = self.keyring.partials()?;
+
+        for r in remnants {
+            let lineage = self.successions.lineage(r.author())?;
+            let trust = self.registry.trust(&lineage).or_else(|| {
+                if ours.iter().any(|a| lineage.contains(a.id())) {
+                    Some(Trust::Trusted)
+                } else {
+                    None
+                }
+            });
+
+            if !policy.admits(trust) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!(
+                        "the {} policy refuses {} by {} author {}",
+                        policy.name(),
+                        r.id(),
+                        trust.map_or("unknown", |t| t.name()),
+                        r.author()
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The public keys of every author in the keyring, every author
+    /// named in a succession and every author in the registry that
+    /// isn't blocked.
+    pub fn known_authors(&self) -> io::Result<Vec<PartialAuthor>> {
+        let mut seen = HashSet::new();
+        let mut known = self.keyring.partials()?;
+        known.extend(self.successions.partials()?);
+        known.extend(self.registry.partials());
+        known.retain(|a| seen.insert(a.id().clone()));
+        Ok(known)
+    }
+
+    /// Keep the public keys of authors we knew nothing about, as known
+    /// authors in the registry. Returns how many were new.
+    pub fn remember_authors(&mut self, authors: &[PartialAuthor], note: &str) -> io::Result<usize> {
+        let known: HashSet<AuthorId> = self
+            .known_authors()?
+            .into_iter()
+            .map(|a| a.id().clone())
+            .collect();
+        let new: Vec<PartialAuthor> = authors
+            .iter()
+            .filter(|a| !known.contains(a.id()))
+            .cloned()
+            .collect();
+
+        let added = self.registry.remember(&new, note)?;
+        if added > 0 {
+            self.registry.save()?;
+        }
+        Ok(added)
+    }
+
+    /// Switch every store in the database to `method` and rewrite
+    /// what's already stored to match. Returns how many items were
+    /// rewritten.
+    pub fn compress(&mut self, method: Compression) -> io::Result<usize> {
+        self.database.set_compression(method)?;
+        self.chunks.set_compression(method)?;
+        self.successions.set_compression(method)?;
+        self.revocations.set_compression(method)?;
+        self.redactions.set_compression(method)?;
+
+        Ok(self.database.recompress()?
+            + self.chunks.recompress()?
+            + self.successions.recompress()?
+            + self.revocations.recompress()?
+            + self.redactions.recompress()?)
+    }
+
+    /// The public key of an author given by keyring label, or by a
+    /// prefix of its id in the keyring or the registry.
+    pub fn public_key(&self, selector: &str) -> io::Result<PartialAuthor> {
+        if let Ok(label) = self.keyring.find(selector) {
+            return Ok(self.keyring.load(&label)?.partial());
+        }
+        match self.registry.find(selector) {
+            Ok(e) => Ok(e.author.clone()),
+            Err(_) => err(&format!("no author matches {}", selector)),
+        }
+    }
+
+    /// The id of an author given as for `public_key`, or in full.
+    pub fn author_id(&self, selector: &str) -> io::Result<AuthorId> {
+        if let Ok(a) = self.public_key(selector) {
+            return Ok(a.id().clone());
+        }
+
+        util::from_hex(selector)
+            .and_then(|b| hash::Digest::from_slice(&b))
+            .map(AuthorId)
+            .map_or_else(|| err(&format!("no author matches {}", selector)), Ok)
+    }
+
+    /// The access policy `allow` describes.
+    pub fn access(&self, allow: &Allow) -> io::Result<Access> {
+        Ok(match allow {
+            Allow::Anyone => Access::Open,
+            Allow::Owner => Access::Owner,
+            Allow::Members(m) => Access::Members(
+                m.iter()
+                    .map(|s| self.author_id(s))
+                    .collect::<io::Result<_>>()?,
+            ),
+        })
+    }
+
+    /// The author to sign new records with.
+    pub fn signer(&self) -> io::Result<&Author> {
+        self.author
+            .as_ref()
+            .map_or_else(|| err("the author's secret key is locked"), Ok)
+    }
 }
 
 pub fn get_plan(a: &ArgMatches) -> io::Result<Plan> {
@@ -37,44 +392,277 @@ pub fn get_plan(a: &ArgMatches) -> io::Result<Plan> {
         h.to_str().unwrap().to_string()
     };
 
-    let author = get_author(&path)?;
-    let database = get_database(&path)?;
+    let format = get_format(a)?;
+    let passphrase = get_passphrase(a)?;
 
-    match a.subcommand() {
+    let command = match a.subcommand() {
         ("append", Some(a)) => cmd_append(a),
         ("origin", Some(o)) => cmd_origin(o),
         ("join", Some(j)) => cmd_join(j),
+        ("attach", Some(a)) => cmd_attach(a),
+        ("attachment", Some(a)) => cmd_attachment(a),
+        ("membership", Some(m)) => cmd_membership(m),
+        ("redact", Some(r)) => cmd_redact(r),
+        ("merge-base", Some(m)) => cmd_merge_base(m),
+        ("is-ancestor", Some(i)) => cmd_is_ancestor(i),
+        ("range", Some(r)) => cmd_range(r),
+        ("log", Some(l)) => cmd_log(l),
+        ("fsck", Some(_)) => Ok(Command::Fsck),
+        ("compress", Some(c)) => cmd_compress(c),
+        ("show", Some(s)) => cmd_show(s),
+        ("export", Some(e)) => cmd_export(e),
+        ("import", Some(i)) => cmd_import(i),
+        ("author", Some(a)) => cmd_author(a),
+        ("trust", Some(t)) => cmd_trust(t),
         (c, _) => err(&format!("unexpected subcommand: {}", c)),
-    }
-    .map(|c| Plan {
+    }?;
+
+    let waiting = || {
+        eprintln!(
+            "waiting for another remnant process to finish with {}",
+            path
+        )
+    };
+    let lock = if command.writes() {
+        triefort::Lock::acquire(Path::new(&path), waiting)?
+    } else {
+        triefort::Lock::acquire_shared(Path::new(&path), waiting)?
+    };
+    let database = match get_cache_limit(a)? {
+        Some(limit) => get_database(&path)?.with_cache(limit),
+        None => get_database(&path)?,
+    };
+    let chunks = get_store(&path, "chunks")?;
+    let successions = get_store(&path, "successions")?;
+    let revocations = get_store(&path, "revocations")?;
+    let redactions = get_store(&path, "redactions")?;
+    let registry = Registry::open(&Path::new(&path).join("registry.json"))?;
+
+    let as_author = a.value_of("as").map(|s| s.to_string());
+    let keyring = get_keyring(&path)?;
+    let author = if command.unlocks() {
+        Some(get_author(&keyring, as_author.as_deref(), &passphrase)?)
+    } else {
+        None
+    };
+
+    Ok(Plan {
         validate: !a.is_present("no-validate"),
-        path: path,
-        command: c,
-        author: author,
-        database: database,
+        format,
+        path,
+        command,
+        passphrase,
+        as_author,
+        keyring,
+        author,
+        database,
+        chunks,
+        successions,
+        revocations,
+        redactions,
+        registry,
+        lock,
     })
 }
 
-fn get_author(path: &str) -> io::Result<Author> {
-    fs::create_dir_all(path)?;
+/// The output format requested with `--format`. Defaults to text.
+pub fn get_format(a: &ArgMatches) -> io::Result<Format> {
+    match a.value_of("format") {
+        None | Some("text") => Ok(Format::Text),
+        Some("json") => Ok(Format::Json),
+        Some(f) => err(&format!("bad format: {}", f)),
+    }
+}
+
+/// Open the keyring, moving a lone `author.json` from before there
+/// were keyrings into it as the default author.
+fn get_keyring(path: &str) -> io::Result<Keyring> {
+    let keyring = Keyring::open(&Path::new(path).join("authors"))?;
 
-    let a_path = Path::new(path).join("author.json");
+    let legacy = Path::new(path).join("author.json");
+    if legacy.exists() && keyring.labels()?.is_empty() {
+        keyring.add(DEFAULT_LABEL, &keyfile::load(&legacy)?)?;
+        fs::remove_file(legacy)?;
+    }
+
+    Ok(keyring)
+}
+
+/// The label of the author picked with `--as`, or of the default
+/// author.
+fn select(keyring: &Keyring, as_author: Option<&str>) -> io::Result<String> {
+    match as_author {
+        Some(s) => keyring.find(s),
+        None => keyring.default()?.map_or_else(
+            || err("no default author; set one with `author default`"),
+            Ok,
+        ),
+    }
+}
 
-    let a = if !a_path.exists() {
+fn get_author(
+    keyring: &Keyring,
+    as_author: Option<&str>,
+    passphrase: &Passphrase,
+) -> io::Result<Author> {
+    if as_author.is_none() && keyring.labels()?.is_empty() {
+        // Only seal a new author when the passphrase was handed to us.
+        // Prompting would surprise someone who never asked for one;
+        // they can run `author encrypt` later.
         let a = Author::new();
-        let a_json = serde_json::to_string(&a)?;
-        let mut a_f = fs::File::create(&a_path)?;
-        a_f.write_all(a_json.as_bytes())?;
+        let file = if passphrase.is_interactive() {
+            AuthorFile::Plain(a.clone())
+        } else {
+            AuthorFile::Sealed(SealedAuthor::seal(&a, passphrase.read("")?.as_bytes())?)
+        };
+        keyring.add(DEFAULT_LABEL, &file)?;
+        keyring.save_revocation(DEFAULT_LABEL, &Revocation::new(&a, None))?;
 
-        a
-    } else {
-        let mut a_f = fs::File::open(&a_path)?;
-        let mut a_str = String::new();
-        a_f.read_to_string(&mut a_str)?;
-        serde_json::from_str(&a_str)?
+        return Ok(a);
+    }
+
+    let label = select(keyring, as_author)?;
+    keyring.unlock(&label, || {
+        passphrase.read(&format!("passphrase for {}: ", label))
+    })
+}
+
+/// Where the passphrase for a sealed author file comes from.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Passphrase {
+    /// Read a line from this file descriptor.
+    Fd(i32),
+
+    /// Use the value of the `REMNANT_PASSPHRASE` environment variable.
+    Env(String),
+
+    /// Ask on the terminal.
+    Prompt,
+}
+
+/// The environment variable holding the passphrase.
+pub const PASSPHRASE_ENV: &str = "REMNANT_PASSPHRASE";
+
+// Keep the passphrase itself out of debug output.
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Passphrase::Fd(fd) => write!(f, "Fd({})", fd),
+            Passphrase::Env(_) => write!(f, "Env"),
+            Passphrase::Prompt => write!(f, "Prompt"),
+        }
+    }
+}
+
+impl Passphrase {
+    pub fn is_interactive(&self) -> bool {
+        *self == Passphrase::Prompt
+    }
+
+    /// Get the passphrase, showing `prompt` if we have to ask.
+    pub fn read(&self, prompt: &str) -> io::Result<String> {
+        match self {
+            Passphrase::Env(p) => Ok(p.clone()),
+            Passphrase::Fd(fd) => read_fd(*fd),
+            Passphrase::Prompt => read_tty(prompt),
+        }
+    }
+}
+
+/// The limit on the database's cache given with `--cache-entries` or
+/// `--cache-bytes`. Zero turns the cache off.
+fn get_cache_limit(a: &ArgMatches) -> io::Result<Option<CacheLimit>> {
+    let limit = match (a.value_of("cache-entries"), a.value_of("cache-bytes")) {
+        (_, Some(b)) => b.parse().map(CacheLimit::Bytes),
+        (Some(n), None) => n.parse().map(CacheLimit::Entries),
+        (None, None) => Ok(CacheLimit::Entries(DEFAULT_CACHE_ENTRIES)),
     };
 
-    Ok(a)
+    match limit {
+        Ok(CacheLimit::Entries(0)) | Ok(CacheLimit::Bytes(0)) => Ok(None),
+        Ok(l) => Ok(Some(l)),
+        Err(_) => err("bad cache size"),
+    }
+}
+
+fn get_passphrase(a: &ArgMatches) -> io::Result<Passphrase> {
+    if let Some(fd) = a.value_of("passphrase-fd") {
+        return fd
+            .parse()
+            .map(Passphrase::Fd)
+            .or_else(|_| err("bad passphrase fd"));
+    }
+
+    Ok(match env::var(PASSPHRASE_ENV) {
+        Ok(p) => Passphrase::Env(p),
+        Err(_) => Passphrase::Prompt,
+    })
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> io::Result<String> {
+    use std::os::unix::io::BorrowedFd;
+
+    if fd < 3 {
+        return err("--passphrase-fd can't be stdin, stdout or stderr");
+    }
+
+    // Read from a duplicate, so the descriptor we were given stays
+    // open for whoever owns it, and a bad one fails here.
+    let dup = unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?;
+    read_line(&mut fs::File::from(dup))
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> io::Result<String> {
+    err("--passphrase-fd is only supported on unix")
+}
+
+fn read_tty(prompt: &str) -> io::Result<String> {
+    let mut tty = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    tty.write_all(prompt.as_bytes())?;
+
+    let hidden = stty(&tty, "-echo");
+    let line = read_line(&mut tty);
+    if hidden {
+        stty(&tty, "echo");
+        tty.write_all(b"\n")?;
+    }
+
+    line
+}
+
+/// Change a terminal setting, returning whether it worked.
+fn stty(tty: &fs::File, arg: &str) -> bool {
+    tty.try_clone()
+        .and_then(|t| process::Command::new("stty").arg(arg).stdin(t).status())
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Read a recovery phrase from the first line of stdin.
+pub fn read_phrase() -> io::Result<String> {
+    read_line(&mut io::stdin())
+}
+
+/// Read up to the end of the first line, one byte at a time so that
+/// nothing past it is consumed.
+fn read_line<R: Read>(r: &mut R) -> io::Result<String> {
+    let mut line = Vec::new();
+
+    let mut b = [0];
+    while r.read(&mut b)? == 1 && b[0] != b'\n' {
+        line.push(b[0]);
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line).or_else(|_| err("passphrase is not UTF-8"))
 }
 
 fn get_database(path: &str) -> io::Result<triefort::Handle<Remnant>> {
@@ -86,17 +674,48 @@ fn get_database(path: &str) -> io::Result<triefort::Handle<Remnant>> {
     Ok(db)
 }
 
+/// Open one of the trieforts kept beside the database.
+fn get_store<T: triefort::Triefort>(path: &str, name: &str) -> io::Result<triefort::Handle<T>> {
+    let triefort_path = Path::new(path).join(name);
+    triefort::open(triefort_path.to_str().unwrap())
+}
+
 fn cmd_append(a: &ArgMatches) -> io::Result<Command> {
     let op = a.value_of("parent");
-    let ob = a.value_of("body");
+    let max = match a.value_of("max-body-size").map(|m| m.parse()) {
+        Some(Ok(m)) => m,
+        Some(Err(_)) => return err("bad max body size"),
+        None => DEFAULT_MAX_BODY_SIZE,
+    };
+
+    // The body comes from the command line, a file, or stdin, in that
+    // order of preference.
+    let body = match (a.value_of("body"), a.value_of("file")) {
+        (Some(b), _) => read_body(b.as_bytes(), max)?,
+        (None, Some(f)) => read_body(fs::File::open(f)?, max)?,
+        (None, None) => read_body(io::stdin(), max)?,
+    };
 
-    match (op, ob) {
-        (Some(p), Some(b)) => Ok(Command::Append {
+    match op {
+        Some(p) => Ok(Command::Append {
             parent: p.to_string(),
-            body: b.as_bytes().to_vec(),
+            body,
         }),
-        (None, _) => err("bad parent"),
-        (_, None) => err("bad body"),
+        None => err("bad parent"),
+    }
+}
+
+/// Read a whole body, failing if it holds more than `max` bytes.
+/// Only `max + 1` bytes are read to find out.
+pub fn read_body<R: Read>(r: R, max: usize) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    r.take((max as u64).saturating_add(1))
+        .read_to_end(&mut body)?;
+
+    if body.len() > max {
+        err(&format!("body is larger than {} bytes", max))
+    } else {
+        Ok(body)
     }
 }
 
@@ -106,11 +725,56 @@ fn cmd_origin(a: &ArgMatches) -> io::Result<Command> {
     match on {
         Some(n) => Ok(Command::Origin {
             name: n.to_string(),
+            allow: get_allow(a),
+            deterministic: a.is_present("deterministic"),
+            meta: get_metadata(a)?,
+            encrypt: a.is_present("encrypt"),
         }),
         None => err("bad name"),
     }
 }
 
+/// The metadata given with `--description`, `--content-type` and
+/// `--tag KEY=VALUE`.
+fn get_metadata(a: &ArgMatches) -> io::Result<Metadata> {
+    let mut meta = Metadata {
+        description: a.value_of("description").map(|d| d.to_string()),
+        content_type: a.value_of("content-type").map(|t| t.to_string()),
+        ..Metadata::default()
+    };
+
+    for t in a.values_of("tag").into_iter().flatten() {
+        match t.find('=') {
+            Some(i) if i > 0 => {
+                meta.tags.insert(t[..i].to_string(), t[i + 1..].to_string());
+            }
+            _ => return err(&format!("bad tag, expected KEY=VALUE: {}", t)),
+        }
+    }
+
+    Ok(meta)
+}
+
+fn cmd_membership(a: &ArgMatches) -> io::Result<Command> {
+    match a.value_of("parent") {
+        Some(p) => Ok(Command::Membership {
+            parent: p.to_string(),
+            allow: get_allow(a),
+        }),
+        None => err("bad parent"),
+    }
+}
+
+/// The access asked for with `--owner-only` or `--member`. Without
+/// either, anyone may add.
+fn get_allow(a: &ArgMatches) -> Allow {
+    match a.values_of("member") {
+        Some(m) => Allow::Members(m.map(|s| s.to_string()).collect()),
+        None if a.is_present("owner-only") => Allow::Owner,
+        None => Allow::Anyone,
+    }
+}
+
 fn cmd_join(a: &ArgMatches) -> io::Result<Command> {
     let ol = a.value_of("left");
     let or = a.value_of("right");
@@ -125,6 +789,285 @@ fn cmd_join(a: &ArgMatches) -> io::Result<Command> {
     }
 }
 
+fn cmd_attach(a: &ArgMatches) -> io::Result<Command> {
+    let op = a.value_of("parent");
+    let of = a.value_of("file");
+
+    match (op, of) {
+        (Some(p), Some(f)) => Ok(Command::Attach {
+            parent: p.to_string(),
+            file: f.to_string(),
+        }),
+        (None, _) => err("bad parent"),
+        (_, None) => err("bad file"),
+    }
+}
+
+fn cmd_attachment(a: &ArgMatches) -> io::Result<Command> {
+    let on = a.value_of("node");
+
+    match on {
+        Some(n) => Ok(Command::Attachment {
+            node: n.to_string(),
+            output: a.value_of("output").map(|o| o.to_string()),
+        }),
+        None => err("bad node"),
+    }
+}
+
+fn cmd_redact(a: &ArgMatches) -> io::Result<Command> {
+    let node = a.value_of("node").map(|n| n.to_string());
+    let request = a.value_of("request").map(|r| r.to_string());
+
+    if node.is_none() && request.is_none() {
+        return err("bad node");
+    }
+
+    Ok(Command::Redact {
+        node,
+        reason: a.value_of("reason").map(|r| r.to_string()),
+        request,
+    })
+}
+
+fn cmd_merge_base(a: &ArgMatches) -> io::Result<Command> {
+    let ol = a.value_of("left");
+    let or = a.value_of("right");
+
+    match (ol, or) {
+        (Some(l), Some(r)) => Ok(Command::MergeBase {
+            left: l.to_string(),
+            right: r.to_string(),
+        }),
+        (None, _) => err("bad left"),
+        (_, None) => err("bad right"),
+    }
+}
+
+fn cmd_is_ancestor(a: &ArgMatches) -> io::Result<Command> {
+    let oa = a.value_of("ancestor");
+    let od = a.value_of("descendant");
+
+    match (oa, od) {
+        (Some(a), Some(d)) => Ok(Command::IsAncestor {
+            ancestor: a.to_string(),
+            descendant: d.to_string(),
+        }),
+        (None, _) => err("bad ancestor"),
+        (_, None) => err("bad descendant"),
+    }
+}
+
+fn cmd_range(a: &ArgMatches) -> io::Result<Command> {
+    let of = a.value_of("from");
+    let ot = a.value_of("to");
+
+    match (of, ot) {
+        (Some(f), Some(t)) => Ok(Command::Range {
+            from: f.to_string(),
+            to: t.to_string(),
+        }),
+        (None, _) => err("bad from"),
+        (_, None) => err("bad to"),
+    }
+}
+
+fn cmd_log(a: &ArgMatches) -> io::Result<Command> {
+    let os = a.value_of("start");
+    let limit = match a.value_of("limit").map(|l| l.parse()) {
+        Some(Ok(l)) => Some(l),
+        Some(Err(_)) => return err("bad limit"),
+        None => None,
+    };
+
+    match os {
+        Some(s) => Ok(Command::Log {
+            start: s.to_string(),
+            limit,
+            author: a.value_of("author").map(|a| a.to_string()),
+            since: a.value_of("since-node").map(|s| s.to_string()),
+        }),
+        None => err("bad start"),
+    }
+}
+
+fn cmd_compress(a: &ArgMatches) -> io::Result<Command> {
+    let method = match a.value_of("method") {
+        None | Some("deflate") => Compression::Deflate,
+        Some("none") => Compression::None,
+        Some(m) => return err(&format!("bad compression method: {}", m)),
+    };
+
+    Ok(Command::Compress { method })
+}
+
+fn cmd_show(a: &ArgMatches) -> io::Result<Command> {
+    let on = a.value_of("node");
+
+    match on {
+        Some(n) => Ok(Command::Show {
+            node: n.to_string(),
+            decrypt: a.is_present("decrypt"),
+        }),
+        None => err("bad node"),
+    }
+}
+
+fn cmd_export(a: &ArgMatches) -> io::Result<Command> {
+    let oh = a.values_of("heads");
+    let oo = a.value_of("output");
+
+    match (oh, oo) {
+        (Some(h), Some(o)) => Ok(Command::Export {
+            heads: h.map(|h| h.to_string()).collect(),
+            output: o.to_string(),
+        }),
+        (None, _) => err("bad heads"),
+        (_, None) => err("bad output"),
+    }
+}
+
+fn cmd_import(a: &ArgMatches) -> io::Result<Command> {
+    let oi = a.value_of("input");
+
+    match oi {
+        Some(i) => Ok(Command::Import {
+            input: i.to_string(),
+            reject_revoked: a.is_present("reject-revoked"),
+            remember_authors: a.is_present("remember-authors"),
+        }),
+        None => err("bad input"),
+    }
+}
+
+fn cmd_author(a: &ArgMatches) -> io::Result<Command> {
+    match a.subcommand() {
+        ("new", Some(n)) => cmd_author_new(n),
+        ("list", _) => Ok(Command::AuthorList),
+        ("default", Some(d)) => Ok(Command::AuthorDefault {
+            label: d.value_of("label").map(|l| l.to_string()),
+        }),
+        ("export-public", Some(e)) => Ok(Command::AuthorExportPublic {
+            output: e.value_of("output").map(|o| o.to_string()),
+            key_format: get_key_format(e)?,
+        }),
+        ("convert-public", Some(c)) => match c.value_of("input") {
+            Some(i) => Ok(Command::AuthorConvertPublic {
+                input: i.to_string(),
+                key_format: get_key_format(c)?,
+            }),
+            None => err("bad input"),
+        },
+        ("encrypt", _) => Ok(Command::AuthorEncrypt),
+        ("decrypt", _) => Ok(Command::AuthorDecrypt),
+        ("rotate", _) => Ok(Command::AuthorRotate),
+        ("mnemonic", _) => Ok(Command::AuthorMnemonic),
+        ("restore", Some(r)) => match cmd_author_new(r)? {
+            Command::AuthorNew {
+                label,
+                default,
+                encrypt,
+            } => Ok(Command::AuthorRestore {
+                label,
+                default,
+                encrypt,
+            }),
+            _ => err("bad label"),
+        },
+        ("revoke", Some(r)) => Ok(Command::AuthorRevoke {
+            last_good: r.value_of("last-good").map(|n| n.to_string()),
+            certificate: r.value_of("certificate").map(|c| c.to_string()),
+        }),
+        ("revocation", Some(r)) => Ok(Command::AuthorRevocation {
+            output: r.value_of("output").map(|o| o.to_string()),
+        }),
+        ("", _) => err("missing author subcommand"),
+        (c, _) => err(&format!("unexpected author subcommand: {}", c)),
+    }
+}
+
+fn cmd_trust(a: &ArgMatches) -> io::Result<Command> {
+    match a.subcommand() {
+        ("add", Some(t)) => match (t.value_of("input"), get_trust(t)?) {
+            (Some(i), trust) => Ok(Command::TrustAdd {
+                input: i.to_string(),
+                trust: trust.unwrap_or(Trust::Trusted),
+                note: t.value_of("note").map(|n| n.to_string()),
+            }),
+            (None, _) => err("bad input"),
+        },
+        ("set", Some(t)) => match (t.value_of("author"), get_trust(t)?) {
+            (Some(a), Some(trust)) => Ok(Command::TrustSet {
+                author: a.to_string(),
+                trust,
+            }),
+            (None, _) => err("bad author"),
+            (_, None) => err("bad trust"),
+        },
+        ("remove", Some(t)) => match t.value_of("author") {
+            Some(a) => Ok(Command::TrustRemove {
+                author: a.to_string(),
+            }),
+            None => err("bad author"),
+        },
+        ("list", _) => Ok(Command::TrustList),
+        ("policy", Some(p)) => match p.value_of("policy") {
+            None => Ok(Command::TrustPolicy { policy: None }),
+            Some(n) => Policy::from_name(n)
+                .map(|p| Command::TrustPolicy { policy: Some(p) })
+                .map_or_else(|| err(&format!("bad policy: {}", n)), Ok),
+        },
+        ("", _) => err("missing trust subcommand"),
+        (c, _) => err(&format!("unexpected trust subcommand: {}", c)),
+    }
+}
+
+fn get_trust(a: &ArgMatches) -> io::Result<Option<Trust>> {
+    match a.value_of("trust") {
+        None => Ok(None),
+        Some(t) => {
+            Trust::from_name(t).map_or_else(|| err(&format!("bad trust: {}", t)), |t| Ok(Some(t)))
+        }
+    }
+}
+
+/// The public key format requested with `--key-format`. Defaults to
+/// JSON.
+fn get_key_format(a: &ArgMatches) -> io::Result<KeyFormat> {
+    match a.value_of("key-format") {
+        None => Ok(KeyFormat::Json),
+        Some(f) => {
+            KeyFormat::from_name(f).map_or_else(|| err(&format!("bad key format: {}", f)), Ok)
+        }
+    }
+}
+
+fn cmd_author_new(a: &ArgMatches) -> io::Result<Command> {
+    let ol = a.value_of("label");
+
+    match ol {
+        Some(l) => Ok(Command::AuthorNew {
+            label: l.to_string(),
+            default: a.is_present("default"),
+            encrypt: a.is_present("encrypt"),
+        }),
+        None => err("bad label"),
+    }
+}
+
 fn err<T>(msg: &str) -> Result<T, io::Error> {
-    Err(io::Error::new(io::ErrorKind::Other, msg))
+    Err(io::Error::other(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_are_limited_in_size() {
+        assert_eq!(b"12345".to_vec(), read_body(&b"12345"[..], 5).unwrap());
+        assert!(read_body(&b"123456"[..], 5).is_err());
+        assert_eq!(Vec::<u8>::new(), read_body(&b""[..], 0).unwrap());
+        assert_eq!(b"any".to_vec(), read_body(&b"any"[..], usize::MAX).unwrap());
+    }
 }
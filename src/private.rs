@@ -0,0 +1,216 @@
+use author::{Author, AuthorId, PartialAuthor};
+use remnant::{Content, NodeId, Remnant};
+use sodiumoxide::crypto::sealedbox;
+use sodiumoxide::crypto::secretbox;
+use sodiumoxide::crypto::sign::ed25519;
+use std::io;
+use triefort;
+
+/// The key of a private timeline, sealed so only one member can open
+/// it. An Origin carries one for each member.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SealedKey {
+    pub member: AuthorId,
+    pub sealed: Vec<u8>,
+}
+
+/// The symmetric key every body of a private timeline is encrypted
+/// with. Bodies are a fresh nonce followed by the secretbox of the
+/// plain text, so ids and signatures cover the ciphertext and anyone
+/// can still verify them.
+///
+/// A timeline has one key for its whole life, sealed to the readers
+/// named by its Origin. A Membership changes who may write, but never
+/// re-keys the timeline: members added later can't read it, and
+/// members removed still can.
+pub struct TimelineKey(secretbox::Key);
+
+impl TimelineKey {
+    pub fn new() -> TimelineKey {
+        TimelineKey(secretbox::gen_key())
+    }
+
+    /// Seal the key to each of `members`. Their signing keys are
+    /// converted to the curve25519 keys sealed boxes use.
+    pub fn seal_for(&self, members: &[PartialAuthor]) -> io::Result<Vec<SealedKey>> {
+        let secretbox::Key(ref k) = self.0;
+
+        members
+            .iter()
+            .map(|m| {
+                let pk = ed25519::to_curve25519_pk(m.pk())
+                    .map_err(|_| invalid(&format!("bad public key for {}", m.id())))?;
+                Ok(SealedKey {
+                    member: m.id().clone(),
+                    sealed: sealedbox::seal(k, &pk),
+                })
+            })
+            .collect()
+    }
+
+    /// Open whichever of `keys` was sealed to `author`.
+    pub fn open(keys: &[SealedKey], author: &Author) -> io::Result<TimelineKey> {
+        let mine = keys
+            .iter()
+            .find(|k| k.member == *author.id())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} is not a reader of the timeline", author.id()),
+                )
+            })?;
+
+        let pk = ed25519::to_curve25519_pk(author.pk()).map_err(|_| invalid("bad public key"))?;
+        let sk = ed25519::to_curve25519_sk(author.sk()).map_err(|_| invalid("bad secret key"))?;
+        let k = sealedbox::open(&mine.sealed, &pk, &sk)
+            .map_err(|_| invalid("the timeline key failed to open"))?;
+
+        secretbox::Key::from_slice(&k)
+            .map(TimelineKey)
+            .ok_or_else(|| invalid("the timeline key is the wrong length"))
+    }
+
+    pub fn encrypt(&self, plain: &[u8]) -> Vec<u8> {
+        let nonce = secretbox::gen_nonce();
+        let mut body = nonce.as_ref().to_vec();
+        body.extend(secretbox::seal(plain, &nonce, &self.0));
+        body
+    }
+
+    pub fn decrypt(&self, body: &[u8]) -> io::Result<Vec<u8>> {
+        if body.len() < secretbox::NONCEBYTES {
+            return Err(invalid("the body is too short to be encrypted"));
+        }
+
+        let (n, sealed) = body.split_at(secretbox::NONCEBYTES);
+        let nonce = secretbox::Nonce::from_slice(n).unwrap();
+        secretbox::open(sealed, &nonce, &self.0).map_err(|_| invalid("the body failed to decrypt"))
+    }
+}
+
+impl Remnant {
+    /// Create a new Append after this one with `plain` encrypted under
+    /// the timeline's key.
+    pub fn append_private(&self, author: &Author, key: &TimelineKey, plain: &[u8]) -> Remnant {
+        self.append(author, &key.encrypt(plain))
+    }
+}
+
+impl triefort::Handle<Remnant> {
+    /// The key of the private timeline `id` belongs to, opened by
+    /// `author`. Returns `None` if the timeline isn't private. This
+    /// walks the whole history of `id` to find its Origin.
+    pub fn timeline_key(&self, id: &NodeId, author: &Author) -> io::Result<Option<TimelineKey>> {
+        for o in self.origins(id)? {
+            if let Content::Origin { keys, .. } = o.content() {
+                if !keys.is_empty() {
+                    return TimelineKey::open(keys, author).map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether `id` is on a private timeline, which anyone can tell
+    /// without being one of its readers.
+    pub fn is_private(&self, id: &NodeId) -> io::Result<bool> {
+        Ok(self.origins(id)?.iter().any(|o| match o.content() {
+            Content::Origin { keys, .. } => !keys.is_empty(),
+            _ => false,
+        }))
+    }
+
+    /// Decrypt the body of an Append on a private timeline.
+    pub fn decrypt(&self, r: &Remnant, author: &Author) -> io::Result<Vec<u8>> {
+        let body = r
+            .content()
+            .body()
+            .ok_or_else(|| invalid(&format!("{} has no body", r.id())))?;
+
+        match self.timeline_key(r.id(), author)? {
+            Some(k) => k.decrypt(body.bytes()),
+            None => Err(invalid(&format!("{} is not on a private timeline", r.id()))),
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use access::Access;
+    use remnant::OriginOptions;
+    use tempdir;
+
+    #[test]
+    fn only_members_can_read_bodies() {
+        let tdir = tempdir::TempDir::new("private_test").unwrap();
+        let db = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let owner = Author::new();
+        let member = Author::new();
+        let outsider = Author::new();
+
+        let key = TimelineKey::new();
+        let opts = OriginOptions {
+            keys: key.seal_for(&[owner.partial(), member.partial()]).unwrap(),
+            ..OriginOptions::default()
+        };
+        let o = Remnant::origin_with(&owner, "secrets", opts);
+        let t = o.append_private(&member, &key, b"the password is hunter2");
+        db.insert(&o).unwrap();
+        db.insert(&t).unwrap();
+
+        // Everyone can still verify what they can't read.
+        t.verify(&member.partial()).unwrap();
+        assert!(!t
+            .content()
+            .body()
+            .unwrap()
+            .bytes()
+            .windows(7)
+            .any(|w| w == b"hunter2"));
+
+        assert_eq!(
+            b"the password is hunter2".to_vec(),
+            db.decrypt(&t, &owner).unwrap()
+        );
+        assert!(db.decrypt(&t, &outsider).is_err());
+
+        let public = Remnant::origin(&owner, "public");
+        db.insert(&public).unwrap();
+        assert!(db.timeline_key(public.id(), &owner).unwrap().is_none());
+    }
+
+    #[test]
+    fn readers_are_fixed_by_the_origin() {
+        let tdir = tempdir::TempDir::new("private_test").unwrap();
+        let db: triefort::Handle<Remnant> = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let owner = Author::new();
+        let late = Author::new();
+
+        let key = TimelineKey::new();
+        let opts = OriginOptions {
+            access: Access::Owner,
+            keys: key.seal_for(&[owner.partial()]).unwrap(),
+            ..OriginOptions::default()
+        };
+        let o = Remnant::origin_with(&owner, "closed", opts);
+        let m = o.membership(&owner, Access::Members(vec![late.id().clone()]));
+        let t = m.append_private(&owner, &key, b"after the door opened");
+        for r in [&o, &m, &t] {
+            db.insert(r).unwrap();
+        }
+        assert!(db.is_private(t.id()).unwrap());
+
+        // The Membership lets late write, but not read.
+        let late_append = t.append(&late, b"hello?");
+        db.permits(&late_append).unwrap();
+        assert!(db.timeline_key(t.id(), &late).is_err());
+        assert!(db.decrypt(&t, &late).is_err());
+    }
+}
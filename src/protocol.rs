@@ -1,5 +1,8 @@
+use attachment;
 use author;
+use redaction;
 use remnant;
+use revocation;
 
 #[derive(Debug)]
 pub enum Request {
@@ -18,6 +21,20 @@ pub enum Request {
 
     /// Advertise a remnant by its Id.
     AdvertiseRemnant(remnant::NodeId),
+
+    /// Ask for every revocation of the specified author.
+    LookupRevocations(author::AuthorId),
+
+    /// Pass on a revocation. Revocations are sent whole rather than
+    /// advertised so that they spread as quickly as possible.
+    AdvertiseRevocation(revocation::Revocation),
+
+    /// Look up a chunk of an attachment by its hash.
+    LookupChunk(attachment::ChunkId),
+
+    /// Pass on a redaction. Like revocations, these are sent whole so
+    /// that bodies are taken down everywhere quickly.
+    AdvertiseRedaction(redaction::Redaction),
 }
 
 #[derive(Debug)]
@@ -34,10 +51,24 @@ pub enum Response {
 
     /// We respond to a remnant lookup request possibly with the
     /// remnant and possibly with nothing.
-    LookupRemnant(Option<remnant::Remnant>),
+    LookupRemnant(Option<Box<remnant::Remnant>>),
 
     /// A trivial response is okay for an advertisement.
     AdvertiseRemnant,
+
+    /// We respond to a revocation lookup with every revocation we
+    /// know of for the author, which may be none.
+    LookupRevocations(Vec<revocation::Revocation>),
+
+    /// A trivial response is okay for a revocation too.
+    AdvertiseRevocation,
+
+    /// We respond to a chunk lookup possibly with the chunk and
+    /// possibly with nothing.
+    LookupChunk(Option<attachment::Chunk>),
+
+    /// A trivial response is okay for a redaction.
+    AdvertiseRedaction,
 }
 
 // What I've got above will work if both peers announce their entire
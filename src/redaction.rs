@@ -0,0 +1,285 @@
+use author::{Author, AuthorId, PartialAuthor};
+use remnant::{Content, NodeId, Remnant, Signature, ValidationErr};
+use std::io;
+use triefort;
+
+/// Prefixed to what a redaction signs.
+const CONTEXT: &[u8] = b"remnant redaction";
+
+/// A signed request to remove the body of an Append. Only the author
+/// of the Append or the owner of a timeline it's on may make one.
+/// Peers that get a redaction replace the node with its redacted form
+/// and keep the redaction so they can pass it on.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Redaction {
+    node: NodeId,
+    by: PartialAuthor,
+    reason: Option<String>,
+    signature: Signature,
+}
+
+impl Redaction {
+    pub fn new(author: &Author, node: &NodeId, reason: Option<&str>) -> Redaction {
+        let msg = statement(node, author.id(), reason);
+
+        Redaction {
+            node: node.clone(),
+            by: author.partial(),
+            reason: reason.map(|r| r.to_string()),
+            signature: Signature::sign(&msg, author),
+        }
+    }
+
+    pub fn node(&self) -> &NodeId {
+        &self.node
+    }
+
+    pub fn by(&self) -> &PartialAuthor {
+        &self.by
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// Check that the public key matches the author id and that the
+    /// author signed the redaction. Whether the author was allowed to
+    /// is up to `Handle::redact`.
+    pub fn verify(&self) -> Result<(), ValidationErr> {
+        let id = AuthorId::from_pk(self.by.pk());
+        if id != *self.by.id() {
+            return Err(ValidationErr::AuthorMismatch(self.by.id().clone(), id));
+        }
+
+        let msg = statement(&self.node, self.by.id(), self.reason());
+        if !self.signature.verify(&msg, &self.by) {
+            return Err(ValidationErr::InvalidSignature(self.signature.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+fn statement(node: &NodeId, by: &AuthorId, reason: Option<&str>) -> Vec<u8> {
+    let mut msg = CONTEXT.to_vec();
+    msg.extend(node.bytes());
+    msg.extend(by.bytes());
+    // Marked, so no reason and an empty one sign different bytes.
+    match reason {
+        Some(r) => {
+            msg.push(1);
+            msg.extend(r.as_bytes());
+        }
+        None => msg.push(0),
+    }
+    msg
+}
+
+/// Redactions are stored under their signature, since more than one
+/// party may ask for the same node to be redacted.
+impl triefort::Triefort for Redaction {
+    fn key(&self) -> &[u8] {
+        self.signature.bytes()
+    }
+}
+
+impl triefort::Handle<Redaction> {
+    /// Verify a redaction and store it. Returns false if it was
+    /// already stored.
+    pub fn record(&self, r: &Redaction) -> io::Result<bool> {
+        r.verify().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("redaction failed to verify: {:?}", e),
+            )
+        })?;
+
+        self.insert_new(r)
+    }
+
+    /// Forget a stored redaction. Returns false if it wasn't stored.
+    pub fn discard(&self, r: &Redaction) -> io::Result<bool> {
+        self.remove(r.signature.bytes())
+    }
+
+    /// The redactions of one node.
+    pub fn of(&self, node: &NodeId) -> io::Result<Vec<Redaction>> {
+        Ok(self
+            .all()?
+            .into_iter()
+            .filter(|r| r.node == *node)
+            .collect())
+    }
+}
+
+impl triefort::Handle<Remnant> {
+    /// Honor a redaction: check it, then replace the node with its
+    /// redacted form. Returns false if the node isn't stored here or
+    /// is already redacted.
+    pub fn redact(&self, red: &Redaction) -> io::Result<bool> {
+        red.verify().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("redaction failed to verify: {:?}", e),
+            )
+        })?;
+
+        if !self.contains(red.node.bytes()) {
+            return Ok(false);
+        }
+
+        let r = self.get(red.node.bytes())?;
+        let redacted = match (r.content(), r.redacted()) {
+            (Content::Append { .. }, Some(redacted)) => redacted,
+            (Content::Append { .. }, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} is a V1 append, whose id covers its body, so it can't be redacted",
+                        red.node
+                    ),
+                ))
+            }
+            (Content::Redacted { .. }, _) => return Ok(false),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not an append, so it has no body to redact", red.node),
+                ))
+            }
+        };
+
+        let by = red.by.id();
+        let owners = self.rules(&red.node)?;
+        if r.author() != by && !owners.iter().any(|rule| rule.owner == *by) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} may not redact {}: only its author or the owner of its timeline may",
+                    by, red.node
+                ),
+            ));
+        }
+
+        self.replace(&redacted)?;
+        Ok(true)
+    }
+
+    /// Apply every stored redaction whose node is here. A redaction
+    /// recorded before its node arrived couldn't be checked then; if it
+    /// turns out not to be allowed, it's dropped from `redactions` and
+    /// returned with the reason, and the rest still apply. Returns how
+    /// many nodes were redacted.
+    pub fn reapply(
+        &self,
+        redactions: &triefort::Handle<Redaction>,
+    ) -> io::Result<(usize, Vec<(Redaction, io::Error)>)> {
+        let mut redacted = 0;
+        let mut refused = Vec::new();
+
+        for red in redactions.all()? {
+            match self.redact(&red) {
+                Ok(true) => redacted += 1,
+                Ok(false) => {}
+                Err(e)
+                    if e.kind() == io::ErrorKind::PermissionDenied
+                        || e.kind() == io::ErrorKind::InvalidInput =>
+                {
+                    redactions.discard(&red)?;
+                    refused.push((red, e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok((redacted, refused))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir;
+
+    #[test]
+    fn authors_and_owners_may_redact() {
+        let tdir = tempdir::TempDir::new("redaction_test").unwrap();
+        let db: triefort::Handle<Remnant> = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let owner = Author::new();
+        let writer = Author::new();
+        let stranger = Author::new();
+
+        let o = Remnant::origin(&owner, "board");
+        let a = o.append(&writer, b"my phone number is 555-0100");
+        let b = a.append(&writer, b"call me");
+        for r in &[&o, &a, &b] {
+            db.insert(r).unwrap();
+        }
+
+        let denied = Redaction::new(&stranger, a.id(), None);
+        assert!(denied.verify().is_ok());
+        assert!(db.redact(&denied).is_err());
+
+        let by_owner = Redaction::new(&owner, a.id(), Some("personal data"));
+        assert!(db.redact(&by_owner).unwrap());
+        assert!(!db.redact(&by_owner).unwrap());
+
+        let stored = db.get(a.id().bytes()).unwrap();
+        assert_eq!("redacted", stored.content().kind());
+        stored.verify(&writer.partial()).unwrap();
+        assert_eq!(vec![b.clone(), stored, o.clone()], db.trace(&b).unwrap());
+
+        let by_writer = Redaction::new(&writer, b.id(), None);
+        assert!(db.redact(&by_writer).unwrap());
+        assert!(db.redact(&Redaction::new(&owner, o.id(), None)).is_err());
+
+        let forged = Redaction {
+            reason: Some("something else".to_string()),
+            ..by_owner
+        };
+        assert!(forged.verify().is_err());
+        let emptied = Redaction {
+            reason: Some(String::new()),
+            ..by_writer
+        };
+        assert!(emptied.verify().is_err());
+    }
+
+    #[test]
+    fn early_redactions_are_checked_when_the_node_arrives() {
+        let tdir = tempdir::TempDir::new("redaction_test").unwrap();
+        let db: triefort::Handle<Remnant> =
+            triefort::open(tdir.path().join("database").to_str().unwrap()).unwrap();
+        let reds: triefort::Handle<Redaction> =
+            triefort::open(tdir.path().join("redactions").to_str().unwrap()).unwrap();
+
+        let writer = Author::new();
+        let o = Remnant::origin(&writer, "late");
+        let a = o.append(&writer, b"first");
+        let b = o.append(&writer, b"second");
+
+        // Neither node is here yet, so neither can be checked.
+        let allowed = Redaction::new(&writer, a.id(), None);
+        let denied = Redaction::new(&Author::new(), b.id(), None);
+        for r in &[&allowed, &denied] {
+            assert!(!db.redact(r).unwrap());
+            assert!(reds.record(r).unwrap());
+        }
+
+        for r in &[&o, &a, &b] {
+            db.insert(r).unwrap();
+        }
+        let (redacted, refused) = db.reapply(&reds).unwrap();
+        assert_eq!(1, redacted);
+        assert_eq!(
+            vec![denied],
+            refused.into_iter().map(|(r, _)| r).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![allowed], reds.all().unwrap());
+        assert_eq!("append", db.get(b.id().bytes()).unwrap().content().kind());
+        let (redacted, refused) = db.reapply(&reds).unwrap();
+        assert_eq!(0, redacted);
+        assert!(refused.is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+use author::{AuthorId, PartialAuthor};
+use serde_json;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use util;
+
+/// How far an author is trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trust {
+    /// Allowed to write into our timelines.
+    Trusted,
+
+    /// Recognised, so their records verify, but not vouched for.
+    Known,
+
+    /// Never accepted.
+    Blocked,
+}
+
+/// Which records the database takes in, by the trust in their
+/// authors. Authors in the keyring count as trusted unless the
+/// registry says otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy {
+    /// Anything that verifies, whoever wrote it.
+    AcceptValid,
+
+    /// Only records by trusted authors.
+    OnlyTrusted,
+
+    /// Anything that verifies, unless its author is blocked.
+    RejectBlocked,
+}
+
+impl Trust {
+    pub fn from_name(name: &str) -> Option<Trust> {
+        match name {
+            "trusted" => Some(Trust::Trusted),
+            "known" => Some(Trust::Known),
+            "blocked" => Some(Trust::Blocked),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Trust::Trusted => "trusted",
+            Trust::Known => "known",
+            Trust::Blocked => "blocked",
+        }
+    }
+}
+
+impl Policy {
+    pub fn from_name(name: &str) -> Option<Policy> {
+        match name {
+            "accept-valid" => Some(Policy::AcceptValid),
+            "only-trusted" => Some(Policy::OnlyTrusted),
+            "reject-blocked" => Some(Policy::RejectBlocked),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::AcceptValid => "accept-valid",
+            Policy::OnlyTrusted => "only-trusted",
+            Policy::RejectBlocked => "reject-blocked",
+        }
+    }
+
+    /// Whether a record by an author with this trust gets in. `None`
+    /// is an author the registry doesn't know.
+    pub fn admits(self, trust: Option<Trust>) -> bool {
+        match self {
+            Policy::AcceptValid => true,
+            Policy::OnlyTrusted => trust == Some(Trust::Trusted),
+            Policy::RejectBlocked => trust != Some(Trust::Blocked),
+        }
+    }
+}
+
+/// An author in the registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub author: PartialAuthor,
+    pub trust: Trust,
+
+    /// Who the author is, for the people reading `trust list`.
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RegistryFile {
+    policy: Policy,
+    authors: Vec<Entry>,
+}
+
+/// The authors we've decided how far to trust, and the policy
+/// applied to records on their way into the database. It's kept as a
+/// JSON file so it can be read and reviewed by hand.
+#[derive(Debug)]
+pub struct Registry {
+    path: PathBuf,
+    file: RegistryFile,
+}
+
+impl Registry {
+    /// Read the registry at `path`. A missing file is an empty
+    /// registry that rejects blocked authors.
+    pub fn open(path: &Path) -> io::Result<Registry> {
+        let file = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(path)?)?
+        } else {
+            RegistryFile {
+                policy: Policy::RejectBlocked,
+                authors: vec![],
+            }
+        };
+
+        Ok(Registry {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Write the registry back. The old file is only replaced once the
+    /// new one is complete.
+    pub fn save(&self) -> io::Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.file)?)?;
+        fs::rename(tmp, &self.path)
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.file.policy
+    }
+
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.file.policy = policy;
+    }
+
+    /// Every author in the registry, in the order they were added.
+    pub fn entries(&self) -> &[Entry] {
+        &self.file.authors
+    }
+
+    pub fn get(&self, id: &AuthorId) -> Option<&Entry> {
+        self.file.authors.iter().find(|e| e.author.id() == id)
+    }
+
+    /// Add an author, or change the trust in one already there. The
+    /// note is only replaced if a new one is given.
+    pub fn set(
+        &mut self,
+        author: PartialAuthor,
+        trust: Trust,
+        note: Option<String>,
+    ) -> io::Result<()> {
+        if !author.check() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the author id does not match the public key",
+            ));
+        }
+
+        match self.file.authors.iter_mut().find(|e| e.author == author) {
+            Some(e) => {
+                e.trust = trust;
+                if note.is_some() {
+                    e.note = note;
+                }
+            }
+            None => self.file.authors.push(Entry {
+                author,
+                trust,
+                note,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Add each of `authors` the registry doesn't have yet as known,
+    /// so their records keep verifying once they're in the database.
+    /// Authors already in the registry keep the trust they have.
+    /// Returns how many were added.
+    pub fn remember(&mut self, authors: &[PartialAuthor], note: &str) -> io::Result<usize> {
+        let mut added = 0;
+        for a in authors {
+            if self.get(a.id()).is_none() {
+                self.set(a.clone(), Trust::Known, Some(note.to_string()))?;
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Forget an author. Returns false if it wasn't in the registry.
+    pub fn remove(&mut self, id: &AuthorId) -> bool {
+        let before = self.file.authors.len();
+        self.file.authors.retain(|e| e.author.id() != id);
+        self.file.authors.len() != before
+    }
+
+    /// Find the one author whose id starts with `prefix` in hex.
+    pub fn find(&self, prefix: &str) -> io::Result<&Entry> {
+        let prefix = prefix.to_lowercase();
+        let mut found = self
+            .file
+            .authors
+            .iter()
+            .filter(|e| util::to_hex(e.author.id().bytes()).starts_with(&prefix));
+
+        match (found.next(), found.next()) {
+            (Some(e), None) => Ok(e),
+            (None, _) => Err(io::Error::other(format!("no author matches {}", prefix))),
+            (Some(_), Some(_)) => Err(io::Error::other(format!(
+                "{} matches more than one author",
+                prefix
+            ))),
+        }
+    }
+
+    /// The trust in an identity given all of its keys, oldest first.
+    /// Blocking any key blocks the identity. Otherwise trust in any key
+    /// carries over to the others, so rotating a key keeps its trust.
+    pub fn trust(&self, lineage: &[AuthorId]) -> Option<Trust> {
+        let levels: Vec<Trust> = lineage
+            .iter()
+            .filter_map(|i| self.get(i).map(|e| e.trust))
+            .collect();
+
+        [Trust::Blocked, Trust::Trusted, Trust::Known]
+            .iter()
+            .find(|t| levels.contains(t))
+            .cloned()
+    }
+
+    /// The public keys of every author that isn't blocked.
+    pub fn partials(&self) -> Vec<PartialAuthor> {
+        self.file
+            .authors
+            .iter()
+            .filter(|e| e.trust != Trust::Blocked)
+            .map(|e| e.author.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+    use tempdir;
+
+    #[test]
+    fn policies_follow_trust() {
+        let p = Policy::OnlyTrusted;
+        assert!(p.admits(Some(Trust::Trusted)));
+        assert!(!p.admits(Some(Trust::Known)));
+        assert!(!p.admits(None));
+
+        let p = Policy::RejectBlocked;
+        assert!(p.admits(None));
+        assert!(!p.admits(Some(Trust::Blocked)));
+
+        assert!(Policy::AcceptValid.admits(Some(Trust::Blocked)));
+    }
+
+    #[test]
+    fn registry_persists_and_follows_lineage() {
+        let tdir = tempdir::TempDir::new("registry_test").unwrap();
+        let path = tdir.path().join("registry.json");
+        let a = Author::new();
+        let b = Author::new();
+
+        let mut r = Registry::open(&path).unwrap();
+        assert_eq!(Policy::RejectBlocked, r.policy());
+        r.set(a.partial(), Trust::Trusted, Some("ops".to_string()))
+            .unwrap();
+        r.set(b.partial(), Trust::Known, None).unwrap();
+        r.set_policy(Policy::OnlyTrusted);
+        r.save().unwrap();
+
+        let mut r = Registry::open(&path).unwrap();
+        assert_eq!(Policy::OnlyTrusted, r.policy());
+        assert_eq!(2, r.entries().len());
+
+        // b is a's rotated key: the identity is trusted until one of
+        // its keys is blocked.
+        let lineage = [a.id().clone(), b.id().clone()];
+        assert_eq!(Some(Trust::Trusted), r.trust(&lineage));
+        r.set(b.partial(), Trust::Blocked, None).unwrap();
+        assert_eq!(Some(Trust::Blocked), r.trust(&lineage));
+        assert_eq!(Some("ops"), r.get(a.id()).unwrap().note.as_deref());
+        assert_eq!(1, r.partials().len());
+
+        let prefix = util::to_hex(a.id().bytes());
+        assert_eq!(a.partial(), r.find(&prefix[..6]).unwrap().author);
+        assert!(r.remove(a.id()));
+        assert!(r.find(&prefix[..6]).is_err());
+    }
+}
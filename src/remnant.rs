@@ -1,13 +1,22 @@
-use author::{Author, AuthorId};
+use access::Access;
+use attachment::Attachment;
+use author::{Author, AuthorId, PartialAuthor};
+use bincode;
+use codec::Codec;
+use private::SealedKey;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sodiumoxide::crypto::hash::sha256 as hash;
 use sodiumoxide::crypto::sign;
+use sodiumoxide::randombytes;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io;
 use triefort;
 use util;
 
 /// The primary storage container for all nodes in a Remnant database.
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Remnant {
     /// The ID of the node. In this implementation, it's a SHA256 of
     /// the author and the content.
@@ -27,16 +36,86 @@ pub struct Remnant {
     signature: Signature,
 }
 
-fn remnant_id_and_sig(author: &Author, content: &Content) -> (NodeId, Signature) {
+/// The length of an Origin's nonce: as long as a UUID.
+pub const NONCE_BYTES: usize = 16;
+
+/// The optional parts of a new Origin.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OriginOptions {
+    /// Random bytes making the Origin unique. Without one, an author
+    /// making two Origins with the same name makes the same node.
+    pub nonce: Option<[u8; NONCE_BYTES]>,
+
+    /// Who may add to the timeline.
+    pub access: Access,
+
+    /// What the timeline is and how to read its bodies.
+    pub meta: Metadata,
+
+    /// The timeline's key sealed to each of its readers, if it's
+    /// private.
+    pub keys: Vec<SealedKey>,
+}
+
+/// What an Origin says about its timeline, signed along with the rest
+/// of it. Everything is optional.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// What the timeline is for.
+    pub description: Option<String>,
+
+    /// How to decode the bodies that follow: a MIME type or the name
+    /// of a schema.
+    pub content_type: Option<String>,
+
+    /// Anything else, as key/value pairs.
+    pub tags: BTreeMap<String, String>,
+}
+
+impl OriginOptions {
+    /// Options for an Origin with a fresh random nonce, open to
+    /// anyone.
+    pub fn unique() -> OriginOptions {
+        OriginOptions {
+            nonce: Some(random_nonce()),
+            ..OriginOptions::default()
+        }
+    }
+}
+
+/// Fresh random bytes for an Origin's nonce.
+pub fn random_nonce() -> [u8; NONCE_BYTES] {
+    let mut nonce = [0; NONCE_BYTES];
+    randombytes::randombytes_into(&mut nonce);
+    nonce
+}
+
+/// Starts what a Membership's id commits to, so it can't be passed off
+/// as an Append with the same parent and a carefully chosen body.
+const MEMBERSHIP_TAG: &[u8] = b"\xffmembership";
+
+/// Starts what an Attachment's id commits to, so a body can't be
+/// passed off as one, or one as a body.
+const ATTACHMENT_TAG: &[u8] = b"\xffattachment";
+
+/// Starts what a V2 Append's id commits to, and a Redacted node's, so
+/// neither can be passed off as a Join of the parent and the digest.
+const APPEND_TAG: &[u8] = b"\xffappend";
+
+fn remnant_id(author: &AuthorId, content: &Content) -> NodeId {
     let mut hasher = hash::State::new();
 
-    hasher.update(author.id().bytes());
+    hasher.update(author.bytes());
     hasher.update(content.bytes().as_slice());
 
-    let nodeid = NodeId(hasher.finalize());
-    let sig = sign::sign_detached(&nodeid.bytes(), &author.sk());
+    NodeId(hasher.finalize())
+}
+
+fn remnant_id_and_sig(author: &Author, content: &Content) -> (NodeId, Signature) {
+    let nodeid = remnant_id(author.id(), content);
+    let sig = Signature::sign(nodeid.bytes(), author);
 
-    (nodeid, Signature(sig))
+    (nodeid, sig)
 }
 
 /// Create a remnant from an Author and a Content.
@@ -46,7 +125,7 @@ pub fn build_remnant(author: &Author, content: Content) -> Remnant {
     Remnant {
         id: nodeid,
         author: author.id().clone(),
-        content: content,
+        content,
         signature: sig,
     }
 }
@@ -60,10 +139,10 @@ pub fn build_remnant_from_parts(
     signature: Signature,
 ) -> Remnant {
     Remnant {
-        id: id,
-        author: author,
-        content: content,
-        signature: signature,
+        id,
+        author,
+        content,
+        signature,
     }
 }
 
@@ -79,6 +158,10 @@ pub enum ValidationErr {
 
     /// The Remnants `Signature` (left) and the computed `Signature` (right)
     SignatureMismatch(Signature, Signature),
+
+    /// The Remnants `Signature` does not verify against the author's
+    /// public key.
+    InvalidSignature(Signature),
 }
 
 impl Remnant {
@@ -102,10 +185,20 @@ impl Remnant {
         &self.signature
     }
 
-    /// Create a new Origin.
+    /// Create a new Origin anyone may add to. It has no nonce, so the
+    /// same author and name always make the same Origin.
     pub fn origin(author: &Author, name: &str) -> Remnant {
+        Remnant::origin_with(author, name, OriginOptions::default())
+    }
+
+    /// Create a new Origin with a nonce, access policy or metadata.
+    pub fn origin_with(author: &Author, name: &str, opts: OriginOptions) -> Remnant {
         let c = Content::Origin {
             name: name.to_string(),
+            nonce: opts.nonce,
+            access: opts.access,
+            meta: opts.meta,
+            keys: opts.keys,
         };
         build_remnant(author, c)
     }
@@ -115,10 +208,33 @@ impl Remnant {
         let c = Content::Append {
             parent: self.id.clone(),
             body: Body(body.to_vec()),
+            version: AppendVersion::V2,
         };
         build_remnant(author, c)
     }
 
+    /// Create a new Append after this one whose body is `value`
+    /// encoded with `codec`.
+    pub fn append_typed<C, T>(&self, author: &Author, codec: C, value: &T) -> io::Result<Remnant>
+    where
+        C: Codec,
+        T: Serialize,
+    {
+        Ok(self.append(author, &codec.encode(value)?))
+    }
+
+    /// Decode the body of an Append with `codec`.
+    pub fn body_as<T, C>(&self, codec: C) -> io::Result<T>
+    where
+        T: DeserializeOwned,
+        C: Codec,
+    {
+        match self.content.body() {
+            Some(b) => codec.decode(b.bytes()),
+            None => err(&format!("{} is not an append", self.id)),
+        }
+    }
+
     /// Create a new Join referencing these two.
     pub fn join(author: &Author, left: &Remnant, right: &Remnant) -> Remnant {
         let c = Content::Join {
@@ -128,6 +244,37 @@ impl Remnant {
         build_remnant(author, c)
     }
 
+    /// Create a Membership after this one, changing who may add to
+    /// the timeline from here on.
+    pub fn membership(&self, author: &Author, access: Access) -> Remnant {
+        let c = Content::Membership {
+            parent: self.id.clone(),
+            access,
+        };
+        build_remnant(author, c)
+    }
+
+    /// A copy of an Append with its body taken out. The copy has the
+    /// same id and signature and still verifies. Returns `None` for
+    /// anything that isn't an Append, and for V1 Appends, whose ids
+    /// commit to the body itself.
+    pub fn redacted(&self) -> Option<Remnant> {
+        match self.content {
+            Content::Append {
+                parent: ref p,
+                body: ref b,
+                version: AppendVersion::V2,
+            } => Some(Remnant {
+                content: Content::Redacted {
+                    parent: p.clone(),
+                    digest: b.digest(),
+                },
+                ..self.clone()
+            }),
+            _ => None,
+        }
+    }
+
     /// Check that the Remnant is valid. The ID and the Signature
     /// should match the other contents in the Remannt.
     pub fn validate(&self, author: &Author) -> Result<(), ValidationErr> {
@@ -149,6 +296,26 @@ impl Remnant {
             Ok(())
         }
     }
+
+    /// Check that the Remnant is valid using only the author's public
+    /// key. The ID should match the other contents in the Remnant and
+    /// the Signature should verify against the ID.
+    pub fn verify(&self, author: &PartialAuthor) -> Result<(), ValidationErr> {
+        let id = remnant_id(author.id(), &self.content);
+
+        if *author.id() != self.author {
+            Err(ValidationErr::AuthorMismatch(
+                self.author.clone(),
+                author.id().clone(),
+            ))
+        } else if id != self.id {
+            Err(ValidationErr::IdentifierMismatch(self.id.clone(), id))
+        } else if !self.signature.verify(id.bytes(), author) {
+            Err(ValidationErr::InvalidSignature(self.signature.clone()))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl fmt::Display for Remnant {
@@ -168,35 +335,99 @@ impl triefort::Triefort for Remnant {
 }
 
 impl triefort::Handle<Remnant> {
-    pub fn trace(&mut self, r: &Remnant) -> io::Result<Vec<Remnant>> {
-        let i = r.id.clone();
-
-        loop {
-            let g = self.get(i.bytes());
+    /// Collect the Remnant and all of its ancestors from the
+    /// database. Each node appears once, and every node appears
+    /// before its parents.
+    pub fn trace(&self, r: &Remnant) -> io::Result<Vec<Remnant>> {
+        self.topological(&self.ancestors(&r.id)?)?
+            .iter()
+            .map(|i| self.get(i.bytes()))
+            .collect()
+    }
 
-            match g {
-                Ok(r) => panic!("found: {:?}", r),
-                Err(e) => panic!("nope: {:?}", e),
+    /// The Origins `id` descends from, itself included if it's one.
+    /// There's more than one when joins reach into other timelines.
+    /// Their metadata says how to read the bodies in between.
+    pub fn origins(&self, id: &NodeId) -> io::Result<Vec<Remnant>> {
+        let mut origins = Vec::new();
+        for i in self.ancestors(id)? {
+            let r = self.get(i.bytes())?;
+            if r.content().parents().is_empty() {
+                origins.push(r);
             }
         }
+        Ok(origins)
+    }
+
+    /// Find the node whose hex identifier starts with `prefix`. The
+    /// prefix must name at least one full byte and match exactly one
+    /// node in the database.
+    pub fn resolve(&self, prefix: &str) -> io::Result<NodeId> {
+        let prefix = prefix.to_lowercase();
+        if !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return err(&format!("bad identifier: {}", prefix));
+        }
+        let even = &prefix[..prefix.len() - prefix.len() % 2];
+
+        let key = match util::from_hex(even) {
+            Some(ref k) if k.is_empty() => return err("identifier prefix is too short"),
+            Some(k) => k,
+            None => return err(&format!("bad identifier: {}", prefix)),
+        };
+
+        let found: Vec<String> = self
+            .find_all_with_prefix(&key)?
+            .into_iter()
+            .filter(|f| f.starts_with(&prefix))
+            .collect();
+
+        match found.len() {
+            0 => err(&format!("no node matches: {}", prefix)),
+            1 => util::from_hex(&found[0])
+                .and_then(|b| NodeId::from_bytes(&b))
+                .map_or_else(|| err(&format!("bad identifier: {}", found[0])), Ok),
+            n => err(&format!("{} nodes match: {}", n, prefix)),
+        }
     }
 }
 
+fn err<T>(msg: &str) -> io::Result<T> {
+    Err(io::Error::other(msg))
+}
+
 /// An identifier for a node that should be unique for a given
 /// timeline. This implementation uses a SHA256 for the Node ID.
-#[derive(PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Clone, Hash)]
 pub struct NodeId(pub hash::Digest);
 
 impl NodeId {
     pub fn bytes(&self) -> &[u8] {
-        let &NodeId(hash::Digest(ref bytes)) = self;
+        let NodeId(hash::Digest(bytes)) = self;
         bytes
     }
+
+    /// Build a NodeId from raw bytes. Returns `None` if the slice is
+    /// not the length of a digest.
+    pub fn from_bytes(bytes: &[u8]) -> Option<NodeId> {
+        hash::Digest::from_slice(bytes).map(NodeId)
+    }
+}
+
+impl Serialize for NodeId {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        util::serialize_hex(s, self.bytes(), &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeId {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<NodeId, D::Error> {
+        util::deserialize_hex(d, hash::Digest::from_slice).map(NodeId)
+    }
 }
 
 impl fmt::Debug for NodeId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let &NodeId(hash::Digest(ref bytes)) = self;
+        let NodeId(hash::Digest(bytes)) = self;
 
         write!(f, "NodeId")?;
         util::debug_bytes(f, bytes)
@@ -205,103 +436,495 @@ impl fmt::Debug for NodeId {
 
 impl fmt::Display for NodeId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let &NodeId(hash::Digest(ref bytes)) = self;
+        let NodeId(hash::Digest(bytes)) = self;
         util::display_bytes(f, bytes)
     }
 }
 
 /// The content variation allowed inside a Remnant.
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(from = "Stored", into = "Stored")]
 pub enum Content {
-    /// The start of a Remnant timeline. It's just a string
-    /// identifying the origin.
-    Origin { name: String },
+    /// The start of a Remnant timeline. It's a string identifying the
+    /// origin, an optional nonce telling apart origins with the same
+    /// name, the policy for who may add to the timeline, metadata
+    /// describing it, and the sealed keys of a private timeline.
+    Origin {
+        name: String,
+        nonce: Option<[u8; NONCE_BYTES]>,
+        access: Access,
+        meta: Metadata,
+        keys: Vec<SealedKey>,
+    },
 
     /// Appends new data to a Remnant timeline. It specifies the
-    /// parent preceeding the node and the body of the node.
-    Append { parent: NodeId, body: Body },
+    /// parent preceeding the node and the body of the node, and how
+    /// the node's id commits to them.
+    Append {
+        parent: NodeId,
+        body: Body,
+        version: AppendVersion,
+    },
 
     /// Appends a record that specifies two nodes as parents. This
     /// gives confidence that children of this node follow both of
     /// this node's parents. More than two nodes can be joined by
     /// chaining/folding joins.
     Join { left: NodeId, right: NodeId },
+
+    /// Changes who may add to the timeline after the parent. Only the
+    /// author of the origin may make one.
+    Membership { parent: NodeId, access: Access },
+
+    /// Adds a file to the timeline after the parent. The file is kept
+    /// in chunks outside of the node, and the node refers to them.
+    Attachment {
+        parent: NodeId,
+        attachment: Attachment,
+    },
+
+    /// A V2 Append whose body has been taken out. The id only ever
+    /// committed to the hash of the body, so keeping the hash keeps
+    /// the id, the signature and every descendant valid.
+    Redacted {
+        parent: NodeId,
+        digest: hash::Digest,
+    },
+}
+
+/// How an Append's id commits to it. New Appends are always V2. V1
+/// Appends come from databases and bundles made before bodies could be
+/// redacted, and still verify.
+///
+/// A V1 id covers the parent and the body with no tag, just as a
+/// Join's covers its two parents. So a V1 Append with a 32 byte body
+/// and a Join of the same two nodes share an id, as they always have.
+/// V2 ids start with a tag and can't be confused with either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppendVersion {
+    /// The id covers the parent and the body. The body can't be
+    /// redacted without changing the id.
+    V1,
+
+    /// The id covers a tag, the parent and the hash of the body.
+    V2,
+}
+
+/// How Content is stored. Origins without a policy keep the layout
+/// they had before origins could have one, so existing databases and
+/// bundles still read. New variants only ever go at the end.
+#[derive(Clone, Serialize, Deserialize)]
+enum Stored {
+    Origin {
+        name: String,
+    },
+    Append {
+        parent: NodeId,
+        body: Body,
+    },
+    Join {
+        left: NodeId,
+        right: NodeId,
+    },
+    Membership {
+        parent: NodeId,
+        access: Access,
+    },
+    Charter {
+        name: String,
+        fields: Vec<Field>,
+    },
+    Redacted {
+        parent: NodeId,
+        digest: hash::Digest,
+    },
+    AppendV2 {
+        parent: NodeId,
+        body: Body,
+    },
+    Attachment {
+        parent: NodeId,
+        attachment: Attachment,
+    },
+}
+
+/// The optional parts of an Origin, as stored. New fields only ever
+/// go at the end.
+#[derive(Clone, Serialize, Deserialize)]
+enum Field {
+    Access(Access),
+    Nonce([u8; NONCE_BYTES]),
+    Description(String),
+    ContentType(String),
+    Tag(String, String),
+    Keys(Vec<SealedKey>),
+}
+
+impl From<Stored> for Content {
+    fn from(s: Stored) -> Content {
+        match s {
+            Stored::Origin { name } => Content::Origin {
+                name,
+                nonce: None,
+                access: Access::Open,
+                meta: Metadata::default(),
+                keys: Vec::new(),
+            },
+            Stored::Append { parent, body } => Content::Append {
+                parent,
+                body,
+                version: AppendVersion::V1,
+            },
+            Stored::AppendV2 { parent, body } => Content::Append {
+                parent,
+                body,
+                version: AppendVersion::V2,
+            },
+            Stored::Attachment { parent, attachment } => Content::Attachment { parent, attachment },
+            Stored::Join { left, right } => Content::Join { left, right },
+            Stored::Membership { parent, access } => Content::Membership { parent, access },
+            Stored::Redacted { parent, digest } => Content::Redacted { parent, digest },
+            Stored::Charter { name, fields } => {
+                let mut nonce = None;
+                let mut access = Access::Open;
+                let mut meta = Metadata::default();
+                let mut keys = Vec::new();
+                for f in fields {
+                    match f {
+                        Field::Access(a) => access = a,
+                        Field::Nonce(n) => nonce = Some(n),
+                        Field::Description(d) => meta.description = Some(d),
+                        Field::ContentType(t) => meta.content_type = Some(t),
+                        Field::Tag(k, v) => {
+                            meta.tags.insert(k, v);
+                        }
+                        Field::Keys(k) => keys = k,
+                    }
+                }
+                Content::Origin {
+                    name,
+                    nonce,
+                    access,
+                    meta,
+                    keys,
+                }
+            }
+        }
+    }
+}
+
+impl From<Content> for Stored {
+    fn from(c: Content) -> Stored {
+        let fields = c.fields();
+        match c {
+            Content::Origin { name, .. } if fields.is_empty() => Stored::Origin { name },
+            Content::Origin { name, .. } => Stored::Charter { name, fields },
+            Content::Append {
+                parent,
+                body,
+                version: AppendVersion::V1,
+            } => Stored::Append { parent, body },
+            Content::Append {
+                parent,
+                body,
+                version: AppendVersion::V2,
+            } => Stored::AppendV2 { parent, body },
+            Content::Attachment { parent, attachment } => Stored::Attachment { parent, attachment },
+            Content::Join { left, right } => Stored::Join { left, right },
+            Content::Membership { parent, access } => Stored::Membership { parent, access },
+            Content::Redacted { parent, digest } => Stored::Redacted { parent, digest },
+        }
+    }
 }
 
 impl fmt::Debug for Content {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Content::Origin { name: ref n } => write!(f, "Content::Origin {{ name: {:?} }}", n),
-            &Content::Append {
-                parent: ref p,
-                body: ref b,
-            } => write!(f, "Content::Append {{ parent: {:?}, body: {:?} }}", p, b),
-            &Content::Join {
-                left: ref l,
-                right: ref r,
-            } => write!(f, "Content::Join( {{ left: {:?}, right: {:?} }}", l, r),
+            Content::Origin {
+                name: n,
+                nonce: o,
+                access: a,
+                meta: m,
+                keys: k,
+            } => write!(
+                f,
+                "Content::Origin {{ name: {:?}, nonce: {:?}, access: {:?}, meta: {:?}, keys: {:?} }}",
+                n, o, a, m, k
+            ),
+            Content::Append {
+                parent: p,
+                body: b,
+                version: v,
+            } => write!(
+                f,
+                "Content::Append {{ parent: {:?}, body: {:?}, version: {:?} }}",
+                p, b, v
+            ),
+            Content::Join { left: l, right: r } => {
+                write!(f, "Content::Join( {{ left: {:?}, right: {:?} }}", l, r)
+            }
+            Content::Membership {
+                parent: p,
+                access: a,
+            } => write!(
+                f,
+                "Content::Membership {{ parent: {:?}, access: {:?} }}",
+                p, a
+            ),
+            Content::Attachment {
+                parent: p,
+                attachment: a,
+            } => write!(
+                f,
+                "Content::Attachment {{ parent: {:?}, attachment: {:?} }}",
+                p, a
+            ),
+            Content::Redacted {
+                parent: p,
+                digest: d,
+            } => write!(
+                f,
+                "Content::Redacted {{ parent: {:?}, digest: {:?} }}",
+                p, d
+            ),
         }
     }
 }
 
 /// A node body used with an Append. It is an arbitrary array of
 /// bytes.
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Body(pub Vec<u8>);
 
 impl Body {
     pub fn bytes(&self) -> &[u8] {
-        let &Body(ref v) = self;
+        let Body(v) = self;
         &v[..]
     }
+
+    /// The hash of the body, which is what the node id commits to.
+    pub fn digest(&self) -> hash::Digest {
+        hash::hash(self.bytes())
+    }
 }
 
+// Bodies are often text, so show them as text when they are. Anything
+// else is shown in hex.
 impl fmt::Debug for Body {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let &Body(ref vec) = self;
-        write!(f, "Body({:?})", String::from_utf8_lossy(vec))
+        let Body(vec) = self;
+        match ::std::str::from_utf8(vec) {
+            Ok(s) => write!(f, "Body({:?})", s),
+            Err(_) => {
+                write!(f, "Body")?;
+                util::debug_bytes(f, vec)
+            }
+        }
     }
 }
 
 /// A signature for the message.
-#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Clone)]
 pub struct Signature(sign::Signature);
 
+impl Signature {
+    /// Sign `msg` with the author's secret key.
+    pub fn sign(msg: &[u8], author: &Author) -> Signature {
+        Signature(sign::sign_detached(msg, author.sk()))
+    }
+
+    /// Check the signature over `msg` against the author's public key.
+    pub fn verify(&self, msg: &[u8], author: &PartialAuthor) -> bool {
+        sign::verify_detached(&self.0, msg, author.pk())
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        let Signature(sig) = self;
+        sig.as_ref()
+    }
+}
+
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        util::serialize_hex(s, self.bytes(), &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Signature, D::Error> {
+        util::deserialize_hex(d, |b| sign::Signature::from_bytes(b).ok()).map(Signature)
+    }
+}
+
 impl fmt::Debug for Signature {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let &Signature(sign::Signature(ref bytes)) = self;
+        let Signature(sig) = self;
         write!(f, "Signature")?;
-        util::debug_bytes(f, bytes)
+        util::debug_bytes(f, sig.as_ref())
     }
 }
 
 impl fmt::Display for Signature {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let &Signature(sign::Signature(ref bytes)) = self;
-        util::display_bytes(f, bytes)
+        let Signature(sig) = self;
+        util::display_bytes(f, sig.as_ref())
     }
 }
 
 impl Content {
+    /// A short lowercase name for the variant: `origin`, `append`,
+    /// `join`, `membership`, `attachment` or `redacted`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Content::Origin { .. } => "origin",
+            Content::Append { .. } => "append",
+            Content::Join { .. } => "join",
+            Content::Membership { .. } => "membership",
+            Content::Attachment { .. } => "attachment",
+            Content::Redacted { .. } => "redacted",
+        }
+    }
+
+    /// The hash of the body of an Append, which a Redacted node keeps.
+    pub fn digest(&self) -> Option<hash::Digest> {
+        match self {
+            Content::Append { body: b, .. } => Some(b.digest()),
+            Content::Redacted { digest: d, .. } => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// The body of an Append.
+    pub fn body(&self) -> Option<&Body> {
+        match self {
+            Content::Append { body: b, .. } => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The file an Attachment refers to.
+    pub fn attachment(&self) -> Option<&Attachment> {
+        match self {
+            Content::Attachment { attachment: a, .. } => Some(a),
+            _ => None,
+        }
+    }
+
+    /// The nodes this content directly follows. An Origin has none,
+    /// an Append, Attachment, Redacted or Membership has one, and a
+    /// Join has two.
+    pub fn parents(&self) -> Vec<&NodeId> {
+        match self {
+            Content::Origin { .. } => vec![],
+            Content::Append { parent: p, .. } => vec![p],
+            Content::Join { left: l, right: r } => vec![l, r],
+            Content::Membership { parent: p, .. } => vec![p],
+            Content::Attachment { parent: p, .. } => vec![p],
+            Content::Redacted { parent: p, .. } => vec![p],
+        }
+    }
+
+    /// The optional fields of an Origin, leaving out the defaults.
+    fn fields(&self) -> Vec<Field> {
+        let mut fields = Vec::new();
+        if let Content::Origin {
+            nonce: n,
+            access: a,
+            meta: m,
+            keys: k,
+            ..
+        } = self
+        {
+            if *a != Access::Open {
+                fields.push(Field::Access(a.clone()));
+            }
+            if let Some(n) = n {
+                fields.push(Field::Nonce(*n));
+            }
+            if let Some(ref d) = m.description {
+                fields.push(Field::Description(d.clone()));
+            }
+            if let Some(ref t) = m.content_type {
+                fields.push(Field::ContentType(t.clone()));
+            }
+            for (k, v) in &m.tags {
+                fields.push(Field::Tag(k.clone(), v.clone()));
+            }
+            if !k.is_empty() {
+                fields.push(Field::Keys(k.clone()));
+            }
+        }
+        fields
+    }
+
+    /// What the node id commits to. An Origin with only a name hashes
+    /// just as it always has. Anything more follows a 0xff byte, which
+    /// can't appear in the UTF-8 name. A V2 Append commits to the hash
+    /// of its body rather than the body, so the body can be redacted,
+    /// and a V1 Append hashes just as it always has.
     fn bytes(&self) -> Vec<u8> {
         match self {
-            &Content::Origin { name: ref n } => n.as_bytes().to_vec(),
-            &Content::Append {
-                parent: ref p,
-                body: ref b,
+            Content::Origin { name: n, .. } => {
+                let mut vec = n.as_bytes().to_vec();
+                let fields = self.fields();
+                if !fields.is_empty() {
+                    vec.push(0xff);
+                    vec.extend(bincode::serialize(&fields).unwrap());
+                }
+                vec
+            }
+            Content::Append {
+                parent: p,
+                body: b,
+                version: AppendVersion::V1,
             } => {
                 let mut vec = p.bytes().to_vec();
                 vec.extend(b.bytes());
                 vec
             }
-            &Content::Join {
-                left: ref l,
-                right: ref r,
+            Content::Append {
+                parent: p,
+                body: b,
+                version: AppendVersion::V2,
+            } => {
+                let mut vec = APPEND_TAG.to_vec();
+                vec.extend(p.bytes());
+                vec.extend(b.digest().as_ref());
+                vec
+            }
+            Content::Redacted {
+                parent: p,
+                digest: d,
             } => {
+                let mut vec = APPEND_TAG.to_vec();
+                vec.extend(p.bytes());
+                vec.extend(d.as_ref());
+                vec
+            }
+            Content::Join { left: l, right: r } => {
                 let mut vec = l.bytes().to_vec();
                 vec.extend(r.bytes());
                 vec
             }
+            Content::Attachment {
+                parent: p,
+                attachment: a,
+            } => {
+                let mut vec = ATTACHMENT_TAG.to_vec();
+                vec.extend(p.bytes());
+                vec.extend(a.root.bytes());
+                vec.extend(&a.size.to_le_bytes());
+                vec
+            }
+            Content::Membership {
+                parent: p,
+                access: a,
+            } => {
+                let mut vec = MEMBERSHIP_TAG.to_vec();
+                vec.extend(p.bytes());
+                vec.extend(bincode::serialize(a).unwrap());
+                vec
+            }
         }
     }
 }
@@ -309,6 +932,9 @@ impl Content {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bincode;
+    use serde_json;
+    use tempdir;
 
     #[test]
     fn it_works() {
@@ -320,12 +946,18 @@ mod tests {
         hasher.update(name.as_bytes());
         let node_id = NodeId(hasher.finalize());
 
-        let sig = Signature(sign::sign_detached(node_id.bytes(), &author.sk()));
+        let sig = Signature(sign::sign_detached(node_id.bytes(), author.sk()));
 
         let expected = Remnant {
             id: node_id,
             author: author.id().clone(),
-            content: Content::Origin { name: name.clone() },
+            content: Content::Origin {
+                name: name.clone(),
+                nonce: None,
+                access: Access::Open,
+                meta: Metadata::default(),
+                keys: vec![],
+            },
             signature: sig,
         };
 
@@ -343,12 +975,20 @@ mod tests {
             &a1,
             Content::Origin {
                 name: "now what".to_string(),
+                nonce: None,
+                access: Access::Open,
+                meta: Metadata::default(),
+                keys: vec![],
             },
         );
         let r2 = build_remnant(
             &a2,
             Content::Origin {
                 name: "now what".to_string(),
+                nonce: None,
+                access: Access::Open,
+                meta: Metadata::default(),
+                keys: vec![],
             },
         );
 
@@ -362,4 +1002,263 @@ mod tests {
 
         j.validate(&a1).unwrap();
     }
+
+    #[test]
+    fn json_uses_hex_ids() {
+        let a = Author::new();
+        let o = Remnant::origin(&a, "hex");
+
+        let json = serde_json::to_value(&o).unwrap();
+        assert_eq!(json!(util::to_hex(o.id().bytes())), json["id"]);
+        assert_eq!(json!(util::to_hex(a.id().bytes())), json["author"]);
+        assert_eq!(
+            json!(util::to_hex(o.signature().bytes())),
+            json["signature"]
+        );
+        assert_eq!(o, serde_json::from_value(json).unwrap());
+
+        // Older JSON wrote ids as arrays of bytes.
+        let old = json!(a.id().bytes());
+        assert_eq!(*a.id(), serde_json::from_value(old).unwrap());
+
+        // Stored objects keep their compact layout.
+        let enc = bincode::serialize(&o).unwrap();
+        assert_eq!(o.id().bytes(), &enc[8..40]);
+        assert_eq!(o, bincode::deserialize(&enc).unwrap());
+    }
+
+    #[test]
+    fn verify_with_only_the_public_key() {
+        let a1 = Author::new();
+        let a2 = Author::new();
+
+        let o = Remnant::origin(&a1, "public");
+        o.verify(&a1.partial()).unwrap();
+
+        assert_eq!(
+            Err(ValidationErr::AuthorMismatch(
+                a1.id().clone(),
+                a2.id().clone()
+            )),
+            o.verify(&a2.partial())
+        );
+
+        let forged = build_remnant_from_parts(
+            o.id().clone(),
+            a1.id().clone(),
+            Content::Origin {
+                name: "public".to_string(),
+                nonce: None,
+                access: Access::Open,
+                meta: Metadata::default(),
+                keys: vec![],
+            },
+            Remnant::origin(&a1, "other").signature().clone(),
+        );
+        assert_eq!(
+            Err(ValidationErr::InvalidSignature(forged.signature().clone())),
+            forged.verify(&a1.partial())
+        );
+    }
+
+    #[test]
+    fn origins_with_a_policy_round_trip() {
+        let a = Author::new();
+        let b = Author::new();
+        let opts = OriginOptions {
+            access: Access::Members(vec![b.id().clone()]),
+            ..OriginOptions::default()
+        };
+        let o = Remnant::origin_with(&a, "club", opts);
+        o.verify(&a.partial()).unwrap();
+        assert_ne!(Remnant::origin(&a, "club").id(), o.id());
+
+        let enc = bincode::serialize(&o).unwrap();
+        assert_eq!(o, bincode::deserialize(&enc).unwrap());
+
+        let m = o.membership(&a, Access::Owner);
+        m.verify(&a.partial()).unwrap();
+        let json = serde_json::to_value(&m).unwrap();
+        assert_eq!(m, serde_json::from_value(json).unwrap());
+
+        // Open origins are stored as they were before origins had a
+        // policy: the content starts with variant 0 after the id and
+        // the author.
+        let open = bincode::serialize(&Remnant::origin(&a, "club")).unwrap();
+        assert_eq!(&[0, 0, 0, 0], &open[80..84]);
+        assert_ne!(&[0, 0, 0, 0], &enc[80..84]);
+    }
+
+    #[test]
+    fn nonces_tell_apart_origins_with_the_same_name() {
+        let a = Author::new();
+        let x = Remnant::origin_with(&a, "todo", OriginOptions::unique());
+        let y = Remnant::origin_with(&a, "todo", OriginOptions::unique());
+        assert_ne!(x.id(), y.id());
+        x.verify(&a.partial()).unwrap();
+
+        let enc = bincode::serialize(&x).unwrap();
+        assert_eq!(x, bincode::deserialize(&enc).unwrap());
+
+        // Without a nonce the same author and name make the same node.
+        assert_eq!(Remnant::origin(&a, "todo"), Remnant::origin(&a, "todo"));
+    }
+
+    #[test]
+    fn metadata_is_signed_and_found_from_bodies() {
+        let tdir = tempdir::TempDir::new("remnant_test").unwrap();
+        let db: triefort::Handle<Remnant> = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let a = Author::new();
+        let mut meta = Metadata {
+            description: Some("sensor readings".to_string()),
+            content_type: Some("application/json".to_string()),
+            ..Metadata::default()
+        };
+        meta.tags.insert("unit".to_string(), "celsius".to_string());
+        let opts = OriginOptions {
+            meta: meta.clone(),
+            ..OriginOptions::default()
+        };
+        let o = Remnant::origin_with(&a, "readings", opts);
+        let t = o.append(&a, b"{\"temp\": 21}");
+        db.insert(&o).unwrap();
+        db.insert(&t).unwrap();
+
+        let found = db.origins(t.id()).unwrap();
+        assert_eq!(vec![o.clone()], found);
+        match found[0].content() {
+            Content::Origin { meta: m, .. } => assert_eq!(meta, *m),
+            c => panic!("not an origin: {:?}", c),
+        }
+
+        let mut altered = meta;
+        altered.content_type = Some("text/plain".to_string());
+        let forged = build_remnant_from_parts(
+            o.id().clone(),
+            a.id().clone(),
+            Content::Origin {
+                name: "readings".to_string(),
+                nonce: None,
+                access: Access::Open,
+                meta: altered,
+                keys: vec![],
+            },
+            o.signature().clone(),
+        );
+        assert!(forged.verify(&a.partial()).is_err());
+    }
+
+    #[test]
+    fn redacted_nodes_keep_their_id() {
+        let a = Author::new();
+        let o = Remnant::origin(&a, "diary");
+        let t = o.append(&a, b"something regrettable");
+        let next = t.append(&a, b"later");
+
+        let r = t.redacted().unwrap();
+        assert_eq!(t.id(), r.id());
+        assert_eq!(None, r.content().body());
+        assert_eq!(t.content().digest(), r.content().digest());
+        r.verify(&a.partial()).unwrap();
+        next.verify(&a.partial()).unwrap();
+        assert!(o.redacted().is_none());
+
+        let enc = bincode::serialize(&r).unwrap();
+        assert_eq!(r, bincode::deserialize(&enc).unwrap());
+
+        // The hash can't be swapped for another body's.
+        let forged = build_remnant_from_parts(
+            t.id().clone(),
+            a.id().clone(),
+            Content::Redacted {
+                parent: o.id().clone(),
+                digest: Body(b"something nicer".to_vec()).digest(),
+            },
+            t.signature().clone(),
+        );
+        assert!(forged.verify(&a.partial()).is_err());
+    }
+
+    #[test]
+    fn resolve_takes_hex_prefixes_only() {
+        let tdir = tempdir::TempDir::new("remnant_test").unwrap();
+        let db: triefort::Handle<Remnant> = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let o = Remnant::origin(&Author::new(), "prefixes");
+        db.insert(&o).unwrap();
+        let hex = util::to_hex(o.id().bytes());
+
+        assert_eq!(*o.id(), db.resolve(&hex[..3]).unwrap());
+        assert_eq!(*o.id(), db.resolve(&hex[..8].to_uppercase()).unwrap());
+        assert!(db.resolve(&hex[..1]).is_err());
+        for bad in &["aé", "é", "zz", &format!("{}g", &hex[..4])] {
+            assert!(db.resolve(bad).is_err());
+        }
+    }
+
+    #[test]
+    fn tags_keep_kinds_apart() {
+        let a = Author::new();
+        let o = Remnant::origin(&a, "tags");
+        let l = o.append(&a, b"left");
+        let r = o.append(&a, b"right");
+        let j = Remnant::join(&a, &l, &r);
+
+        let relabelled = build_remnant_from_parts(
+            j.id().clone(),
+            a.id().clone(),
+            Content::Redacted {
+                parent: l.id().clone(),
+                digest: r.id().0,
+            },
+            j.signature().clone(),
+        );
+        assert!(relabelled.verify(&a.partial()).is_err());
+
+        let as_join = build_remnant_from_parts(
+            l.id().clone(),
+            a.id().clone(),
+            Content::Join {
+                left: o.id().clone(),
+                right: NodeId(l.content().digest().unwrap()),
+            },
+            l.signature().clone(),
+        );
+        assert!(as_join.verify(&a.partial()).is_err());
+    }
+
+    #[test]
+    fn v1_appends_still_verify() {
+        let a = Author::new();
+        let o = Remnant::origin(&a, "old");
+
+        // How Appends were hashed and stored before they had versions.
+        let mut hasher = hash::State::new();
+        hasher.update(a.id().bytes());
+        hasher.update(o.id().bytes());
+        hasher.update(b"from long ago");
+        let id = NodeId(hasher.finalize());
+
+        #[derive(Serialize)]
+        enum Old {
+            _Origin { name: String },
+            Append { parent: NodeId, body: Body },
+        }
+        let content = bincode::serialize(&Old::Append {
+            parent: o.id().clone(),
+            body: Body(b"from long ago".to_vec()),
+        })
+        .unwrap();
+        let mut enc = bincode::serialize(&id).unwrap();
+        enc.extend(bincode::serialize(a.id()).unwrap());
+        enc.extend(content);
+        enc.extend(bincode::serialize(&Signature::sign(id.bytes(), &a)).unwrap());
+
+        let old: Remnant = bincode::deserialize(&enc).unwrap();
+        old.verify(&a.partial()).unwrap();
+        assert_eq!(b"from long ago", old.content().body().unwrap().bytes());
+        assert!(old.redacted().is_none());
+        assert_eq!(enc, bincode::serialize(&old).unwrap());
+    }
 }
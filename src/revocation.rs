@@ -0,0 +1,208 @@
+use author::{Author, AuthorId, PartialAuthor};
+use remnant::{NodeId, Remnant, Signature, ValidationErr};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use triefort;
+
+/// Prefixed to what a revocation signs.
+const CONTEXT: &[u8] = b"remnant revocation";
+
+/// A declaration, signed by an author, that its key is compromised.
+/// Remnants by the author that are ancestors of `last_good` are still
+/// trusted; everything else it signed is suspect. A certificate made
+/// when the key is created can't know which node that will be, so it
+/// has no `last_good` and revokes everything.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Revocation {
+    author: PartialAuthor,
+    last_good: Option<NodeId>,
+    signature: Signature,
+}
+
+impl Revocation {
+    /// Revoke the author's key, trusting only `last_good` and its
+    /// ancestors.
+    pub fn new(author: &Author, last_good: Option<&NodeId>) -> Revocation {
+        let msg = statement(author.id(), last_good);
+
+        Revocation {
+            author: author.partial(),
+            last_good: last_good.cloned(),
+            signature: Signature::sign(&msg, author),
+        }
+    }
+
+    pub fn author(&self) -> &PartialAuthor {
+        &self.author
+    }
+
+    pub fn last_good(&self) -> Option<&NodeId> {
+        self.last_good.as_ref()
+    }
+
+    /// Check that the public key matches the author id and that the
+    /// author signed the revocation.
+    pub fn verify(&self) -> Result<(), ValidationErr> {
+        let id = AuthorId::from_pk(self.author.pk());
+        if id != *self.author.id() {
+            return Err(ValidationErr::AuthorMismatch(self.author.id().clone(), id));
+        }
+
+        let msg = statement(self.author.id(), self.last_good.as_ref());
+        if !self.signature.verify(&msg, &self.author) {
+            return Err(ValidationErr::InvalidSignature(self.signature.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+fn statement(author: &AuthorId, last_good: Option<&NodeId>) -> Vec<u8> {
+    let mut msg = CONTEXT.to_vec();
+    msg.extend(author.bytes());
+    if let Some(n) = last_good {
+        msg.extend(n.bytes());
+    }
+    msg
+}
+
+/// Revocations are stored under their signature. An author can have
+/// several, and a Remnant is suspect if any of them says so.
+impl triefort::Triefort for Revocation {
+    fn key(&self) -> &[u8] {
+        self.signature.bytes()
+    }
+}
+
+impl triefort::Handle<Revocation> {
+    /// Verify a revocation and store it. Returns false if it was
+    /// already stored.
+    pub fn record(&self, r: &Revocation) -> io::Result<bool> {
+        r.verify().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("revocation failed to verify: {:?}", e),
+            )
+        })?;
+
+        self.insert_new(r)
+    }
+
+    /// The revocations of one author.
+    pub fn of(&self, author: &AuthorId) -> io::Result<Vec<Revocation>> {
+        Ok(self
+            .all()?
+            .into_iter()
+            .filter(|r| r.author.id() == author)
+            .collect())
+    }
+}
+
+/// Find the Remnants among `remnants` that one of `revocations`
+/// makes suspect, along with the revocation. Parents are looked up in
+/// `remnants` before `db`, so a bundle can be checked before it's
+/// imported.
+pub fn suspects<'a>(
+    db: &triefort::Handle<Remnant>,
+    remnants: &[Remnant],
+    revocations: &'a [Revocation],
+) -> io::Result<Vec<(NodeId, &'a Revocation)>> {
+    let local: HashMap<&NodeId, &Remnant> = remnants.iter().map(|r| (r.id(), r)).collect();
+
+    let mut trusted = Vec::with_capacity(revocations.len());
+    for rev in revocations {
+        trusted.push(match rev.last_good {
+            Some(ref n) => good(db, &local, n)?,
+            None => HashSet::new(),
+        });
+    }
+
+    let mut found = Vec::new();
+    for r in remnants {
+        let by = revocations
+            .iter()
+            .zip(&trusted)
+            .find(|(rev, t)| rev.author.id() == r.author() && !t.contains(r.id()));
+
+        if let Some((rev, _)) = by {
+            found.push((r.id().clone(), rev));
+        }
+    }
+
+    Ok(found)
+}
+
+/// `last_good` and all of its ancestors. Nodes missing from both
+/// `local` and `db` end the walk along their branch.
+fn good(
+    db: &triefort::Handle<Remnant>,
+    local: &HashMap<&NodeId, &Remnant>,
+    last_good: &NodeId,
+) -> io::Result<HashSet<NodeId>> {
+    let mut seen = HashSet::new();
+    let mut todo = vec![last_good.clone()];
+
+    while let Some(i) = todo.pop() {
+        if seen.contains(&i) {
+            continue;
+        }
+
+        let parents = match local.get(&i) {
+            Some(r) => r.content().parents().into_iter().cloned().collect(),
+            None if db.contains(i.bytes()) => db.parents(&i)?,
+            None => continue,
+        };
+
+        todo.extend(parents);
+        seen.insert(i);
+    }
+
+    Ok(seen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir;
+
+    #[test]
+    fn revocations_are_signed_by_the_author() {
+        let a = Author::new();
+        let b = Author::new();
+
+        let r = Revocation::new(&a, None);
+        assert_eq!(Ok(()), r.verify());
+
+        let forged = Revocation {
+            author: b.partial(),
+            ..r.clone()
+        };
+        assert!(forged.verify().is_err());
+    }
+
+    #[test]
+    fn only_history_before_the_last_good_node_is_trusted() {
+        let tdir = tempdir::TempDir::new("revocation_test").unwrap();
+        let db: triefort::Handle<Remnant> = triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let a = Author::new();
+        let b = Author::new();
+        let o = Remnant::origin(&a, "leaky");
+        let good = o.append(&a, b"before the leak");
+        let bad = good.append(&a, b"after the leak");
+        let other = good.append(&b, b"someone else");
+
+        db.insert(&o).unwrap();
+        db.insert(&good).unwrap();
+
+        // `bad` and `other` are only in the slice, as if in a bundle.
+        let remnants = vec![o, good, bad, other];
+
+        let revs = vec![Revocation::new(&a, Some(remnants[1].id()))];
+        let found = suspects(&db, &remnants, &revs).unwrap();
+        assert_eq!(vec![(remnants[2].id().clone(), &revs[0])], found);
+
+        let revs = vec![Revocation::new(&a, None)];
+        assert_eq!(3, suspects(&db, &remnants, &revs).unwrap().len());
+    }
+}
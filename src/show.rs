@@ -0,0 +1,211 @@
+use access::Access;
+use author::{AuthorId, PartialAuthor};
+use remnant::{Content, Remnant, ValidationErr};
+use revocation::Revocation;
+use std::fmt::Write;
+use util;
+
+/// Bytes shown on each line of a hexdump.
+const HEXDUMP_WIDTH: usize = 16;
+
+/// Check the Remnant against whichever of `authors` wrote it.
+/// Returns `None` if the author isn't among them.
+pub fn verification(r: &Remnant, authors: &[PartialAuthor]) -> Option<Result<(), ValidationErr>> {
+    authors
+        .iter()
+        .find(|a| a.id() == r.author())
+        .map(|a| r.verify(a))
+}
+
+/// Describe every field of a Remnant, one per line. The signature is
+/// checked against whichever of `authors` wrote the Remnant, if any.
+/// `identity` is the first key of the author's identity, and is shown
+/// when the key has been rotated since. `revoked` is the revocation
+/// making the Remnant suspect, if any. `plain` is the decrypted body
+/// of an Append on a private timeline, shown in place of the
+/// ciphertext.
+pub fn show(
+    r: &Remnant,
+    authors: &[PartialAuthor],
+    identity: &AuthorId,
+    revoked: Option<&Revocation>,
+    plain: Option<&[u8]>,
+) -> String {
+    let mut s = String::new();
+
+    let verified = match verification(r, authors) {
+        Some(Ok(())) => "yes".to_string(),
+        Some(Err(e)) => format!("no ({:?})", e),
+        None => "unknown author".to_string(),
+    };
+
+    let _ = writeln!(s, "node      {}", util::to_hex(r.id().bytes()));
+    let _ = writeln!(s, "author    {}", util::to_hex(r.author().bytes()));
+    if identity != r.author() {
+        let _ = writeln!(s, "identity  {}", util::to_hex(identity.bytes()));
+    }
+    let _ = writeln!(s, "signature {}", util::to_hex(r.signature().bytes()));
+    let _ = writeln!(s, "verified  {}", verified);
+    match revoked.map(|rev| rev.last_good()) {
+        Some(Some(n)) => {
+            let _ = writeln!(s, "revoked   yes (only trusted up to {})", n);
+        }
+        Some(None) => {
+            let _ = writeln!(s, "revoked   yes (key revoked entirely)");
+        }
+        None => {}
+    }
+    let _ = writeln!(s, "content   {}", r.content().kind());
+
+    for p in r.content().parents() {
+        let _ = writeln!(s, "parent    {}", util::to_hex(p.bytes()));
+    }
+
+    match r.content() {
+        Content::Origin {
+            name: n,
+            nonce: o,
+            access: a,
+            meta: m,
+            keys: k,
+        } => {
+            let _ = writeln!(s, "name      {}", n);
+            if let Some(o) = o {
+                let _ = writeln!(s, "nonce     {}", util::to_hex(o));
+            }
+            if let Some(ref d) = m.description {
+                let _ = writeln!(s, "about     {}", d);
+            }
+            if let Some(ref t) = m.content_type {
+                let _ = writeln!(s, "type      {}", t);
+            }
+            for (k, v) in &m.tags {
+                let _ = writeln!(s, "tag       {}={}", k, v);
+            }
+            access(&mut s, a);
+            for i in k {
+                let _ = writeln!(s, "reader    {}", util::to_hex(i.member.bytes()));
+            }
+        }
+        Content::Append { body: b, .. } => match plain {
+            Some(p) => {
+                let _ = writeln!(
+                    s,
+                    "body      {} bytes, {} decrypted",
+                    b.bytes().len(),
+                    p.len()
+                );
+                s.push('\n');
+                s.push_str(&body(p));
+            }
+            None => {
+                let _ = writeln!(s, "body      {} bytes", b.bytes().len());
+                s.push('\n');
+                s.push_str(&body(b.bytes()));
+            }
+        },
+        Content::Attachment { attachment: a, .. } => {
+            let _ = writeln!(
+                s,
+                "attached  {} bytes in {}",
+                a.size,
+                util::to_hex(a.root.bytes())
+            );
+        }
+        Content::Join { .. } => {}
+        Content::Membership { access: a, .. } => access(&mut s, a),
+        Content::Redacted { digest: d, .. } => {
+            let _ = writeln!(s, "body      redacted");
+            let _ = writeln!(s, "sha256    {}", util::to_hex(d.as_ref()));
+        }
+    }
+
+    s
+}
+
+/// Who may add to the timeline, with a line for each member.
+fn access(s: &mut String, a: &Access) {
+    match a {
+        Access::Open => {
+            let _ = writeln!(s, "access    open");
+        }
+        Access::Owner => {
+            let _ = writeln!(s, "access    owner only");
+        }
+        Access::Members(m) => {
+            let _ = writeln!(s, "access    owner and members");
+            for i in m {
+                let _ = writeln!(s, "member    {}", util::to_hex(i.bytes()));
+            }
+        }
+    }
+}
+
+/// The body as text if it's UTF-8, otherwise as a hexdump.
+fn body(bytes: &[u8]) -> String {
+    match ::std::str::from_utf8(bytes) {
+        Ok(t) if t.ends_with('\n') || t.is_empty() => t.to_string(),
+        Ok(t) => format!("{}\n", t),
+        Err(_) => hexdump(bytes),
+    }
+}
+
+/// Lay out bytes like `xxd`: an offset, the bytes in hex, and the
+/// printable ASCII characters.
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut s = String::new();
+
+    for (n, line) in bytes.chunks(HEXDUMP_WIDTH).enumerate() {
+        let _ = write!(s, "{:08x}  ", n * HEXDUMP_WIDTH);
+
+        for i in 0..HEXDUMP_WIDTH {
+            match line.get(i) {
+                Some(b) => {
+                    let _ = write!(s, "{:02x} ", b);
+                }
+                None => s.push_str("   "),
+            }
+        }
+
+        s.push('|');
+        for &b in line {
+            s.push(if (0x20..0x7f).contains(&b) {
+                b as char
+            } else {
+                '.'
+            });
+        }
+        s.push_str("|\n");
+    }
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+
+    #[test]
+    fn shows_text_and_binary_bodies() {
+        let a = Author::new();
+        let o = Remnant::origin(&a, "inspect");
+        let t = o.append(&a, b"plain text");
+        let b = o.append(&a, &[0x00, 0x41, 0xff]);
+
+        let shown = show(&t, &[a.partial()], a.id(), None, None);
+        assert!(shown.contains("verified  yes\n"));
+        assert!(shown.contains(&format!("parent    {}\n", util::to_hex(o.id().bytes()))));
+        assert!(shown.ends_with("\nplain text\n"));
+        assert!(!shown.contains("identity"));
+        assert!(!shown.contains("revoked"));
+
+        let first = Author::new();
+        let rev = Revocation::new(&a, Some(t.id()));
+        let shown = show(&b, &[], first.id(), Some(&rev), None);
+        assert!(shown.contains(&format!("revoked   yes (only trusted up to {})\n", t.id())));
+        assert!(shown.contains(&format!("identity  {}\n", util::to_hex(first.id().bytes()))));
+        assert!(shown.contains("verified  unknown author\n"));
+        assert!(shown.ends_with(&format!("\n00000000  00 41 ff {:39}|.A.|\n", "")));
+    }
+}
@@ -0,0 +1,309 @@
+use author::{Author, AuthorId, PartialAuthor};
+use remnant::{Signature, ValidationErr};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use triefort;
+
+/// Prefixed to what a succession signs.
+const CONTEXT: &[u8] = b"remnant succession";
+
+/// A declaration that the key `old` has been replaced by the key
+/// `new`. The old key signs the pair of ids and the new key
+/// counter-signs that signature, so each key agrees to the succession.
+/// Remnants signed by either key belong to the same logical identity.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Succession {
+    old: PartialAuthor,
+    new: PartialAuthor,
+
+    /// The old key's signature over both ids.
+    old_signature: Signature,
+
+    /// The new key's signature over both ids and `old_signature`.
+    new_signature: Signature,
+}
+
+impl Succession {
+    /// Declare that `new` succeeds `old`.
+    pub fn new(old: &Author, new: &Author) -> Succession {
+        let msg = statement(old.id(), new.id());
+        let old_signature = Signature::sign(&msg, old);
+        let new_signature = Signature::sign(&countersigned(&msg, &old_signature), new);
+
+        Succession {
+            old: old.partial(),
+            new: new.partial(),
+            old_signature,
+            new_signature,
+        }
+    }
+
+    /// The key being retired.
+    pub fn retired(&self) -> &PartialAuthor {
+        &self.old
+    }
+
+    /// The key taking over.
+    pub fn replacement(&self) -> &PartialAuthor {
+        &self.new
+    }
+
+    /// Check that both public keys match their ids and that both
+    /// signatures verify.
+    pub fn verify(&self) -> Result<(), ValidationErr> {
+        for a in &[&self.old, &self.new] {
+            let id = AuthorId::from_pk(a.pk());
+            if id != *a.id() {
+                return Err(ValidationErr::AuthorMismatch(a.id().clone(), id));
+            }
+        }
+
+        let msg = statement(self.old.id(), self.new.id());
+        if !self.old_signature.verify(&msg, &self.old) {
+            return Err(ValidationErr::InvalidSignature(self.old_signature.clone()));
+        }
+
+        let msg = countersigned(&msg, &self.old_signature);
+        if !self.new_signature.verify(&msg, &self.new) {
+            return Err(ValidationErr::InvalidSignature(self.new_signature.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+fn statement(old: &AuthorId, new: &AuthorId) -> Vec<u8> {
+    let mut msg = CONTEXT.to_vec();
+    msg.extend(old.bytes());
+    msg.extend(new.bytes());
+    msg
+}
+
+fn countersigned(statement: &[u8], old_signature: &Signature) -> Vec<u8> {
+    let mut msg = statement.to_vec();
+    msg.extend(old_signature.bytes());
+    msg
+}
+
+/// Successions are stored under the id of the key they retire, so a
+/// key can only ever have one successor.
+impl triefort::Triefort for Succession {
+    fn key(&self) -> &[u8] {
+        self.old.id().bytes()
+    }
+}
+
+impl triefort::Handle<Succession> {
+    /// Verify a succession and store it. Returns false if the same
+    /// succession was already stored.
+    pub fn record(&self, s: &Succession) -> io::Result<bool> {
+        s.verify()
+            .map_err(|e| invalid(format!("succession failed to verify: {:?}", e)))?;
+
+        if let Some(existing) = self.successor(s.old.id())? {
+            return if existing == *s {
+                Ok(false)
+            } else {
+                Err(invalid(format!("{} already has a successor", s.old.id())))
+            };
+        }
+
+        if self.lineage(s.old.id())?.contains(s.new.id()) {
+            return Err(invalid(format!(
+                "{} already precedes {}",
+                s.new.id(),
+                s.old.id()
+            )));
+        }
+
+        self.insert(s)?;
+        Ok(true)
+    }
+
+    /// Check that every succession in `batch` verifies and that all of
+    /// them could be recorded, alongside what's stored and each other,
+    /// without giving a key two successors or forming a loop. Nothing
+    /// is stored.
+    pub fn check_batch(&self, batch: &[Succession]) -> io::Result<()> {
+        for s in batch {
+            s.verify().map_err(|e| {
+                invalid(format!(
+                    "succession of {} failed to verify: {:?}",
+                    s.old.id(),
+                    e
+                ))
+            })?;
+        }
+
+        let stored = self.all()?;
+        let mut next: HashMap<&AuthorId, &Succession> = HashMap::new();
+        for s in stored.iter().chain(batch) {
+            match next.get(s.old.id()) {
+                Some(existing) if *existing != s => {
+                    return Err(invalid(format!("{} already has a successor", s.old.id())))
+                }
+                Some(_) => {}
+                None => {
+                    next.insert(s.old.id(), s);
+                }
+            }
+        }
+
+        for s in batch {
+            let mut at = s.new.id();
+            for _ in 0..=next.len() {
+                if at == s.old.id() {
+                    return Err(invalid(format!(
+                        "{} already precedes {}",
+                        s.new.id(),
+                        s.old.id()
+                    )));
+                }
+                match next.get(at) {
+                    Some(n) => at = n.new.id(),
+                    None => break,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The succession retiring `id`, if there is one.
+    pub fn successor(&self, id: &AuthorId) -> io::Result<Option<Succession>> {
+        if self.contains(id.bytes()) {
+            self.get(id.bytes()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The succession that introduced `id`, if there is one.
+    pub fn predecessor(&self, id: &AuthorId) -> io::Result<Option<Succession>> {
+        Ok(self.all()?.into_iter().find(|s| s.new.id() == id))
+    }
+
+    /// Every key of the identity `id` belongs to, oldest first. A key
+    /// that was never rotated is an identity of its own.
+    pub fn lineage(&self, id: &AuthorId) -> io::Result<Vec<AuthorId>> {
+        let mut seen = HashSet::new();
+        seen.insert(id.clone());
+
+        let mut before = Vec::new();
+        let mut at = id.clone();
+        while let Some(s) = self.predecessor(&at)? {
+            at = s.old.id().clone();
+            if !seen.insert(at.clone()) {
+                break;
+            }
+            before.push(at.clone());
+        }
+        before.reverse();
+
+        let mut lineage = before;
+        lineage.push(id.clone());
+
+        let mut at = id.clone();
+        while let Some(s) = self.successor(&at)? {
+            at = s.new.id().clone();
+            if !seen.insert(at.clone()) {
+                break;
+            }
+            lineage.push(at.clone());
+        }
+
+        Ok(lineage)
+    }
+
+    /// The first key of the identity `id` belongs to. It names the
+    /// identity for as long as the keys keep being rotated.
+    pub fn identity(&self, id: &AuthorId) -> io::Result<AuthorId> {
+        Ok(self.lineage(id)?.remove(0))
+    }
+
+    /// The newest key of the identity `id` belongs to.
+    pub fn current(&self, id: &AuthorId) -> io::Result<AuthorId> {
+        Ok(self.lineage(id)?.pop().unwrap_or_else(|| id.clone()))
+    }
+
+    /// The public keys of every author named in a succession. These
+    /// are enough to verify history signed by retired keys.
+    pub fn partials(&self) -> io::Result<Vec<PartialAuthor>> {
+        let mut seen = HashSet::new();
+        let mut partials = Vec::new();
+        for s in self.all()? {
+            for a in [s.old, s.new] {
+                if seen.insert(a.id().clone()) {
+                    partials.push(a);
+                }
+            }
+        }
+        Ok(partials)
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir;
+
+    #[test]
+    fn successions_must_be_signed_by_both_keys() {
+        let a = Author::new();
+        let b = Author::new();
+        let c = Author::new();
+
+        let s = Succession::new(&a, &b);
+        assert_eq!(Ok(()), s.verify());
+
+        let forged = Succession {
+            new: c.partial(),
+            ..s.clone()
+        };
+        assert!(forged.verify().is_err());
+
+        let mut swapped = Succession::new(&a, &c);
+        swapped.new_signature = s.new_signature.clone();
+        assert!(swapped.verify().is_err());
+    }
+
+    #[test]
+    fn lineage_follows_the_chain() {
+        let tdir = tempdir::TempDir::new("succession_test").unwrap();
+        let db: triefort::Handle<Succession> =
+            triefort::open(tdir.path().to_str().unwrap()).unwrap();
+
+        let a = Author::new();
+        let b = Author::new();
+        let c = Author::new();
+
+        assert!(db.record(&Succession::new(&b, &c)).unwrap());
+        assert!(db.record(&Succession::new(&a, &b)).unwrap());
+        assert!(!db.record(&Succession::new(&a, &b)).unwrap());
+        assert!(db.record(&Succession::new(&a, &c)).is_err());
+        assert!(db.record(&Succession::new(&c, &a)).is_err());
+
+        let d = Author::new();
+        let e = Author::new();
+        assert!(db.check_batch(&[Succession::new(&c, &d)]).is_ok());
+        assert!(db.check_batch(&[Succession::new(&a, &d)]).is_err());
+        let looped = [Succession::new(&d, &e), Succession::new(&e, &d)];
+        assert!(db.check_batch(&looped).is_err());
+        assert!(db
+            .check_batch(&[Succession::new(&c, &d), Succession::new(&c, &e)])
+            .is_err());
+        assert_eq!(2, db.keys().unwrap().len());
+
+        let lineage = vec![a.id().clone(), b.id().clone(), c.id().clone()];
+        assert_eq!(lineage, db.lineage(b.id()).unwrap());
+        assert_eq!(*a.id(), db.identity(c.id()).unwrap());
+        assert_eq!(*c.id(), db.current(a.id()).unwrap());
+        assert_eq!(3, db.partials().unwrap().len());
+
+        assert_eq!(vec![d.id().clone()], db.lineage(d.id()).unwrap());
+    }
+}
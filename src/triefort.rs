@@ -1,13 +1,26 @@
 use std::default;
+use std::error;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crc32fast;
+use flate2;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use fs2::FileExt;
+use lru::LruCache;
 
 use bincode;
 use serde;
 use serde_json;
+use util;
 
 const DEFAULT_LEVELS: usize = 2;
 
@@ -18,6 +31,232 @@ struct Config {
     /// Levels the number of levels to use. This is how many
     /// sub-directories will be created.
     levels: usize,
+
+    /// How items are compressed when they're written. Items already
+    /// written keep whatever compression they had.
+    #[serde(default)]
+    compression: Compression,
+}
+
+/// How a triefort compresses the items it writes. Every file says
+/// which was used, so files written with different settings can sit
+/// side by side.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+/// The bytes naming each compression.
+const PLAIN: u8 = 0;
+const DEFLATE: u8 = 1;
+
+impl Compression {
+    fn byte(self) -> u8 {
+        match self {
+            Compression::None => PLAIN,
+            Compression::Deflate => DEFLATE,
+        }
+    }
+
+    fn compress(self, item: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => item.to_vec(),
+            Compression::Deflate => {
+                let mut e = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                e.write_all(item).unwrap();
+                e.finish().unwrap()
+            }
+        }
+    }
+}
+
+/// The most a compressed item may inflate to: the default body limit
+/// with room for the rest of a Remnant. Larger items are stored
+/// uncompressed, so anything that inflates past this is refused rather
+/// than allowed to fill memory.
+const MAX_INFLATED_SIZE: usize = 1024 * 1024 + 64 * 1024;
+
+fn inflate(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut v = Vec::new();
+    DeflateDecoder::new(bytes)
+        .take(MAX_INFLATED_SIZE as u64 + 1)
+        .read_to_end(&mut v)
+        .ok()?;
+    if v.len() > MAX_INFLATED_SIZE {
+        return None;
+    }
+    Some(v)
+}
+
+/// The first bytes of every file written as a record.
+const RECORD_MAGIC: &[u8; 4] = b"TFRT";
+
+/// The version of the record format written now.
+const RECORD_VERSION: u8 = 1;
+
+/// Magic, version, compression, payload length and checksum.
+const RECORD_HEADER: usize = 4 + 1 + 1 + 8 + 4;
+
+/// A stored item wrapped so damage can be told apart from an item
+/// that merely fails to decode: the magic number, the format version,
+/// the compression, the length of the payload, a CRC-32 over all of
+/// those and the payload, and then the compressed item itself.
+#[derive(Debug, PartialEq, Eq)]
+struct Record<'a> {
+    compression: u8,
+    payload: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    fn write(compression: Compression, item: &[u8]) -> Vec<u8> {
+        let compression = if item.len() > MAX_INFLATED_SIZE {
+            Compression::None
+        } else {
+            compression
+        };
+        Record::wrap(compression.byte(), &compression.compress(item))
+    }
+
+    fn wrap(compression: u8, payload: &[u8]) -> Vec<u8> {
+        let mut v = Vec::with_capacity(RECORD_HEADER + payload.len());
+        v.extend(RECORD_MAGIC);
+        v.push(RECORD_VERSION);
+        v.push(compression);
+        v.extend(&(payload.len() as u64).to_le_bytes());
+        let crc = checksum(&v[RECORD_MAGIC.len()..], payload);
+        v.extend(&crc.to_le_bytes());
+        v.extend(payload);
+        v
+    }
+
+    /// The record in `stored`, or `None` if it isn't one. Files that
+    /// start with the magic number must be whole and match their
+    /// checksum.
+    fn read(stored: &'a [u8]) -> Result<Option<Record<'a>>, LoadError> {
+        if !stored.starts_with(RECORD_MAGIC) {
+            return Ok(None);
+        }
+        if stored.len() < RECORD_HEADER {
+            return Err(LoadError::Format(
+                "the record header is cut short".to_string(),
+            ));
+        }
+
+        let (header, payload) = stored.split_at(RECORD_HEADER);
+        let version = header[4];
+        if version != RECORD_VERSION {
+            return Err(LoadError::Format(format!(
+                "unknown record version {}",
+                version
+            )));
+        }
+
+        let mut n = [0; 8];
+        n.copy_from_slice(&header[6..14]);
+        let length = u64::from_le_bytes(n);
+        if length != payload.len() as u64 {
+            return Err(LoadError::Format(format!(
+                "the record holds {} bytes but says it holds {}",
+                payload.len(),
+                length
+            )));
+        }
+
+        let mut c = [0; 4];
+        c.copy_from_slice(&header[14..]);
+        let stored_crc = u32::from_le_bytes(c);
+        let actual_crc = checksum(&header[4..14], payload);
+        if stored_crc != actual_crc {
+            return Err(LoadError::Checksum {
+                stored: stored_crc,
+                actual: actual_crc,
+            });
+        }
+
+        Ok(Some(Record {
+            compression: header[5],
+            payload,
+        }))
+    }
+}
+
+fn checksum(header: &[u8], payload: &[u8]) -> u32 {
+    let mut h = crc32fast::Hasher::new();
+    h.update(header);
+    h.update(payload);
+    h.finalize()
+}
+
+/// The encoded item in a stored file. Files written before records
+/// are just the item.
+fn unpack(stored: &[u8]) -> Result<Vec<u8>, LoadError> {
+    match Record::read(stored)? {
+        Some(r) => match r.compression {
+            PLAIN => Ok(r.payload.to_vec()),
+            DEFLATE => inflate(r.payload).ok_or(LoadError::Decode),
+            c => Err(LoadError::Format(format!("unknown compression {}", c))),
+        },
+        None => Ok(stored.to_vec()),
+    }
+}
+
+/// Why `Handle::load` couldn't produce an item.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file couldn't be read, or isn't there.
+    Io(io::Error),
+
+    /// The file is a record, but a malformed one.
+    Format(String),
+
+    /// The file is a record whose checksum doesn't match its contents,
+    /// so it was damaged after it was written.
+    Checksum { stored: u32, actual: u32 },
+
+    /// The file is intact, or predates checksums, but doesn't decode.
+    Decode,
+
+    /// The item decoded but failed its check against the key.
+    Check,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Format(m) => write!(f, "bad record: {}", m),
+            LoadError::Checksum { stored, actual } => write!(
+                f,
+                "checksum mismatch: stored {:08x}, computed {:08x}",
+                stored, actual
+            ),
+            LoadError::Decode => write!(f, "Item failed to decode."),
+            LoadError::Check => write!(f, "Item failed check."),
+        }
+    }
+}
+
+impl error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
+impl From<LoadError> for io::Error {
+    fn from(e: LoadError) -> io::Error {
+        match e {
+            LoadError::Io(e) => e,
+            LoadError::Format(_) | LoadError::Checksum { .. } => {
+                io::Error::new(io::ErrorKind::InvalidData, e)
+            }
+            LoadError::Decode | LoadError::Check => io::Error::other(e.to_string()),
+        }
+    }
 }
 
 impl Config {
@@ -29,7 +268,7 @@ impl Config {
         let mut p = PathBuf::new();
 
         for c in key.chunks(1).take(self.levels) {
-            let hex = to_hex(c);
+            let hex = util::to_hex(c);
             p.push(hex);
         }
 
@@ -41,17 +280,118 @@ impl default::Default for Config {
     fn default() -> Self {
         Config {
             levels: DEFAULT_LEVELS,
+            compression: Compression::None,
         }
     }
 }
 
+/// A triefort on disk. Reads take `&self` and every write lands in
+/// one atomic rename or link, so a handle can be shared between
+/// threads, and several handles can open the same directory.
 #[derive(Debug)]
 pub struct Handle<T> {
     cfg: Config,
     root: String,
+    cache: Option<Mutex<Cache<T>>>,
     _phantom: PhantomData<T>,
 }
 
+/// How large a Handle's cache of decoded items may grow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheLimit {
+    /// At most this many items.
+    Entries(usize),
+
+    /// Items whose stored sizes add up to at most this many bytes.
+    Bytes(usize),
+}
+
+/// How a Handle's cache is doing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+/// Recently read items, decoded and checked, along with their stored
+/// sizes. The least recently used go first when the cache is full.
+/// The generation counts forgets, so a read that raced with one isn't
+/// put back afterwards.
+struct Cache<T> {
+    limit: CacheLimit,
+    items: LruCache<Vec<u8>, (T, usize)>,
+    stats: CacheStats,
+    generation: u64,
+}
+
+impl<T: Clone> Cache<T> {
+    fn new(limit: CacheLimit) -> Cache<T> {
+        Cache {
+            limit,
+            items: LruCache::unbounded(),
+            stats: CacheStats::default(),
+            generation: 0,
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<T> {
+        match self.items.get(key) {
+            Some((i, _)) => {
+                self.stats.hits += 1;
+                Some(i.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Keep an item read while the cache was at generation `seen`.
+    /// If anything has been forgotten since, the read may be stale and
+    /// the item is dropped instead.
+    fn put(&mut self, key: &[u8], item: T, size: usize, seen: u64) {
+        if seen != self.generation {
+            return;
+        }
+        if let Some((_, (_, old))) = self.items.push(key.to_vec(), (item, size)) {
+            self.stats.bytes -= old;
+        }
+        self.stats.bytes += size;
+
+        while self.full() {
+            match self.items.pop_lru() {
+                Some((_, (_, s))) => self.stats.bytes -= s,
+                None => break,
+            }
+        }
+        self.stats.entries = self.items.len();
+    }
+
+    fn full(&self) -> bool {
+        match self.limit {
+            CacheLimit::Entries(n) => self.items.len() > n,
+            CacheLimit::Bytes(b) => self.stats.bytes > b,
+        }
+    }
+
+    fn forget(&mut self, key: &[u8]) {
+        self.generation += 1;
+        if let Some((_, s)) = self.items.pop(key) {
+            self.stats.bytes -= s;
+        }
+        self.stats.entries = self.items.len();
+    }
+}
+
+impl<T> fmt::Debug for Cache<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cache({:?}, {:?})", self.limit, self.stats)
+    }
+}
+
 pub fn open<T: Triefort>(path: &str) -> Result<Handle<T>, io::Error> {
     let p = Path::new(path);
     let p_cfg = p.join("config.json");
@@ -65,36 +405,90 @@ pub fn open<T: Triefort>(path: &str) -> Result<Handle<T>, io::Error> {
         fs::create_dir_all(p)?;
 
         let default_cfg = Config::default();
-        let default_cfg_json = serde_json::to_string_pretty(&default_cfg).unwrap();
-        let mut f_cfg = fs::File::create(&p_cfg)?;
-        f_cfg.write_all(default_cfg_json.as_bytes())?;
+        save_config(p, &default_cfg)?;
 
         default_cfg
     };
 
     Ok(Handle {
-        cfg: cfg,
+        cfg,
         root: path.to_string(),
+        cache: None,
         _phantom: PhantomData,
     })
 }
 
-fn to_hex(bytes: &[u8]) -> String {
-    let mut hex = String::with_capacity(bytes.len() * 2);
+fn save_config(dir: &Path, cfg: &Config) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(cfg).unwrap();
+    let tmp_path = write_temp(dir, "config.json", json.as_bytes())?;
+    fs::rename(tmp_path, dir.join("config.json"))
+}
+
+fn err<T>(msg: &str) -> io::Result<T> {
+    Err(io::Error::other(msg))
+}
+
+/// Write `bytes` to a new file in `dir` that no other thread or
+/// process will pick, to be linked or renamed into place.
+fn write_temp(dir: &Path, name: &str, bytes: &[u8]) -> io::Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let tmp_path = dir.join(format!("{}.{}.{}.tmp", name, process::id(), n));
+    let mut f = fs::File::create(&tmp_path)?;
+    f.write_all(bytes)?;
+    f.sync_all()?;
+    Ok(tmp_path)
+}
+
+/// An advisory lock on a directory, held until it's dropped. Any
+/// number of readers may share it, but a writer holds it alone and
+/// waits for, or is waited on by, everyone else.
+#[derive(Debug)]
+pub struct Lock(fs::File);
 
-    for b in bytes {
-        hex.push_str(&format!("{:02x}", b));
+impl Lock {
+    /// Take the lock file in `dir` to write, waiting if another
+    /// process has it. `waiting` is called first if we have to wait.
+    pub fn acquire<F: FnOnce()>(dir: &Path, waiting: F) -> io::Result<Lock> {
+        let f = Lock::file(dir)?;
+        if f.try_lock_exclusive().is_err() {
+            waiting();
+            f.lock_exclusive()?;
+        }
+        Ok(Lock(f))
     }
 
-    hex
+    /// Like `acquire`, but only to read, so other readers aren't kept
+    /// waiting.
+    pub fn acquire_shared<F: FnOnce()>(dir: &Path, waiting: F) -> io::Result<Lock> {
+        let f = Lock::file(dir)?;
+        if f.try_lock_shared().is_err() {
+            waiting();
+            f.lock_shared()?;
+        }
+        Ok(Lock(f))
+    }
+
+    fn file(dir: &Path) -> io::Result<fs::File> {
+        fs::create_dir_all(dir)?;
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join("lock"))
+    }
 }
 
-fn err<T>(msg: &str) -> io::Result<T> {
-    Err(io::Error::new(io::ErrorKind::Other, msg))
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
 }
 
 fn add_files(root: &PathBuf, key: &[u8], paths: &mut Vec<String>) {
-    let hex = to_hex(key);
+    let hex = util::to_hex(key);
     let _ = fs::read_dir(root).map(|rd| {
         for d in rd {
             let _ = d.map(|p| {
@@ -103,9 +497,9 @@ fn add_files(root: &PathBuf, key: &[u8], paths: &mut Vec<String>) {
                     add_files(&root.join(&path), key, paths);
                 } else {
                     let filename = path.as_path().file_name().unwrap().to_str().unwrap();
-                    let c = &filename[0..hex.len()];
 
-                    if c == hex {
+                    // Anything with a dot is a write still in progress.
+                    if filename.starts_with(&hex) && !filename.contains('.') {
                         paths.push(filename.to_string());
                     }
                 }
@@ -119,7 +513,7 @@ fn files_matching<'a, T>(hdl: &'a Handle<T>, key: &'a [u8]) -> io::Result<Vec<St
     root.push(&hdl.root);
 
     for c in key.chunks(1).take(hdl.cfg.levels) {
-        root.push(&to_hex(c));
+        root.push(util::to_hex(c));
     }
 
     let mut file_paths: Vec<String> = Vec::new();
@@ -128,8 +522,90 @@ fn files_matching<'a, T>(hdl: &'a Handle<T>, key: &'a [u8]) -> io::Result<Vec<St
     Ok(file_paths)
 }
 
+fn add_keys(dir: &Path, depth: usize, keys: &mut Vec<Vec<u8>>) -> io::Result<()> {
+    for d in fs::read_dir(dir)? {
+        let path = d?.path();
+
+        if depth > 0 && path.is_dir() {
+            add_keys(&path, depth - 1, keys)?;
+        } else if depth == 0 && path.is_file() {
+            let key = path
+                .file_name()
+                .and_then(|f| f.to_str())
+                .and_then(util::from_hex);
+
+            if let Some(k) = key {
+                keys.push(k);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 impl<T: Triefort> Handle<T> {
-    pub fn insert(&mut self, item: &T) -> io::Result<()> {
+    /// Keep recently read items in memory, decoded, up to `limit`.
+    pub fn with_cache(mut self, limit: CacheLimit) -> Handle<T> {
+        self.cache = Some(Mutex::new(Cache::new(limit)));
+        self
+    }
+
+    /// Hits and misses of the cache, if the handle has one.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|c| c.lock().unwrap().stats)
+    }
+
+    /// How items written from now on are compressed.
+    pub fn compression(&self) -> Compression {
+        self.cfg.compression
+    }
+
+    /// Compress items written from now on with `c`. This is kept in
+    /// the triefort's config. Use `recompress` to rewrite the items
+    /// already there.
+    pub fn set_compression(&mut self, c: Compression) -> io::Result<()> {
+        self.cfg.compression = c;
+        save_config(Path::new(&self.root), &self.cfg)
+    }
+
+    /// Rewrite every item as a record with the current compression.
+    /// Each item is replaced on its own, so readers can carry on
+    /// meanwhile and an interrupted run leaves every item readable.
+    /// Returns how many items were rewritten.
+    pub fn recompress(&self) -> io::Result<usize> {
+        let mut n = 0;
+        for k in self.keys()? {
+            let item = self.get(&k)?;
+            if self.read(&k)? != self.pack(&item) {
+                self.replace(&item)?;
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    fn pack(&self, item: &T) -> Vec<u8> {
+        Record::write(self.cfg.compression, &item.encode())
+    }
+
+    /// Decode a stored file. The flag says whether the item passes
+    /// the check against `key`.
+    fn unpack(&self, stored: &[u8], key: &[u8]) -> Result<(T, bool), LoadError> {
+        let i = T::try_decode(&unpack(stored)?).ok_or(LoadError::Decode)?;
+        let checked = i.check(key);
+        Ok((i, checked))
+    }
+
+    fn forget(&self, key: &[u8]) {
+        if let Some(ref c) = self.cache {
+            c.lock().unwrap().forget(key);
+        }
+    }
+
+    /// Store a new item. Inserting a key that's already stored fails,
+    /// even when two threads or processes race to insert it: exactly
+    /// one of them succeeds and the item is never half written.
+    pub fn insert(&self, item: &T) -> io::Result<()> {
         let k = item.key();
 
         if k.len() < self.cfg.min_key_size() {
@@ -141,50 +617,150 @@ impl<T: Triefort> Handle<T> {
         // that trieforts are transferrable.
 
         let dir_path = Path::new(&self.root).join(self.cfg.dir_from_key(k));
-        let item_path = dir_path.join(to_hex(k));
+        let item_path = dir_path.join(util::to_hex(k));
 
         if item_path.exists() {
-            err("Item already exists.")
-        } else {
-            fs::create_dir_all(dir_path)?;
-            let mut f = fs::File::create(item_path)?;
-            f.write_all(&item.encode())?;
-            Ok(())
+            return err("Item already exists.");
+        }
+
+        fs::create_dir_all(&dir_path)?;
+        let tmp_path = write_temp(&dir_path, &util::to_hex(k), &self.pack(item))?;
+
+        // Linking fails if the name is taken, which settles races.
+        let linked = fs::hard_link(&tmp_path, &item_path);
+        fs::remove_file(&tmp_path)?;
+        match linked {
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => err("Item already exists."),
+            r => r,
         }
     }
 
-    pub fn get(&mut self, key: &[u8]) -> io::Result<T> {
-        let i: T = self.get_unchecked(key)?;
-        if !i.check(key) {
-            err("Item failed check.")
-        } else {
-            Ok(i)
+    /// Insert an item unless one is already stored under its key.
+    /// Returns false if one was.
+    pub fn insert_new(&self, item: &T) -> io::Result<bool> {
+        if self.contains(item.key()) {
+            return Ok(false);
         }
+        self.insert(item)?;
+        Ok(true)
     }
 
-    pub fn get_unchecked(&mut self, key: &[u8]) -> io::Result<T> {
-        let p = Path::new(&self.root)
+    /// Overwrite an item that has already been inserted with one
+    /// under the same key. The new item is written beside the old one
+    /// and renamed over it, so readers see one or the other.
+    pub fn replace(&self, item: &T) -> io::Result<()> {
+        let k = item.key();
+        let dir_path = Path::new(&self.root).join(self.cfg.dir_from_key(k));
+        let item_path = dir_path.join(util::to_hex(k));
+
+        if !item_path.exists() {
+            return err("Item not in triefort.");
+        }
+
+        let tmp_path = write_temp(&dir_path, &util::to_hex(k), &self.pack(item))?;
+        fs::rename(tmp_path, item_path)?;
+        self.forget(k);
+        Ok(())
+    }
+
+    /// Delete an item. Returns false if there was nothing to delete.
+    pub fn remove(&self, key: &[u8]) -> io::Result<bool> {
+        // Forget only once the file is gone, so a read can't put the
+        // item back in between.
+        let removed = match fs::remove_file(self.path(key)) {
+            Ok(()) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.forget(key);
+        removed
+    }
+
+    /// Check whether an item with this key has been inserted.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.path(key).exists()
+    }
+
+    /// Read an item and check it against its key. Checked items are
+    /// kept in the cache, if there is one.
+    pub fn get(&self, key: &[u8]) -> io::Result<T> {
+        self.load(key).map_err(io::Error::from)
+    }
+
+    /// Like `get`, but says exactly what went wrong: whether the file
+    /// was damaged on disk, as opposed to holding something that
+    /// doesn't decode or isn't the item asked for.
+    pub fn load(&self, key: &[u8]) -> Result<T, LoadError> {
+        let mut seen = 0;
+        if let Some(ref c) = self.cache {
+            let mut c = c.lock().unwrap();
+            if let Some(i) = c.get(key) {
+                return Ok(i);
+            }
+            seen = c.generation;
+        }
+
+        let v = self.read(key)?;
+        let (i, checked) = self.unpack(&v, key)?;
+        if !checked {
+            return Err(LoadError::Check);
+        }
+
+        if let Some(ref c) = self.cache {
+            c.lock().unwrap().put(key, i.clone(), v.len(), seen);
+        }
+        Ok(i)
+    }
+
+    pub fn get_unchecked(&self, key: &[u8]) -> io::Result<T> {
+        let v = self.read(key)?;
+        Ok(self.unpack(&v, key)?.0)
+    }
+
+    fn path(&self, key: &[u8]) -> PathBuf {
+        Path::new(&self.root)
             .join(self.cfg.dir_from_key(key))
-            .join(to_hex(key));
+            .join(util::to_hex(key))
+    }
+
+    fn read(&self, key: &[u8]) -> io::Result<Vec<u8>> {
+        let p = self.path(key);
 
         if p.exists() {
             let mut v = Vec::new();
             let mut fh = fs::File::open(p)?;
             let _ = fh.read_to_end(&mut v).unwrap();
-            Ok(T::decode(&v))
+            Ok(v)
         } else {
             err("Item not in triefort.")
         }
     }
 
-    pub fn find_all_with_prefix<'a>(&'a mut self, key: &'a [u8]) -> io::Result<Vec<String>> {
+    pub fn find_all_with_prefix<'a>(&'a self, key: &'a [u8]) -> io::Result<Vec<String>> {
         files_matching(self, key)
     }
+
+    /// The keys of every item stored in the triefort, in no
+    /// particular order.
+    pub fn keys(&self) -> io::Result<Vec<Vec<u8>>> {
+        let mut keys = Vec::new();
+        add_keys(Path::new(&self.root), self.cfg.levels, &mut keys)?;
+        Ok(keys)
+    }
+
+    /// Every stored item, checked, in no particular order.
+    pub fn all(&self) -> io::Result<Vec<T>> {
+        let mut all = Vec::new();
+        for k in self.keys()? {
+            all.push(self.get(&k)?);
+        }
+        Ok(all)
+    }
 }
 
 pub trait Triefort
 where
-    Self: serde::Serialize + serde::de::DeserializeOwned,
+    Self: serde::Serialize + serde::de::DeserializeOwned + Clone,
 {
     fn encode(&self) -> Vec<u8> {
         bincode::serialize(self).unwrap()
@@ -194,6 +770,11 @@ where
         bincode::deserialize(enc).unwrap()
     }
 
+    /// Like `decode`, but `None` if `enc` isn't an encoded item.
+    fn try_decode(enc: &[u8]) -> Option<Self> {
+        bincode::deserialize(enc).ok()
+    }
+
     fn check(&self, key: &[u8]) -> bool {
         self.key() == key
     }
@@ -206,7 +787,7 @@ mod tests {
     use super::*;
     use tempdir;
 
-    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
     struct Thing {
         key: Vec<u8>,
     }
@@ -220,7 +801,7 @@ mod tests {
     #[test]
     fn it_works() {
         let tdir = tempdir::TempDir::new("triefort_test").unwrap();
-        let mut hdl = open::<Thing>(tdir.path().to_str().unwrap()).unwrap();
+        let hdl = open::<Thing>(tdir.path().to_str().unwrap()).unwrap();
         println!("hdl: {:?}", hdl);
 
         let t1_key = vec![1, 2, 3, 4];
@@ -253,7 +834,7 @@ mod tests {
     #[test]
     fn find_all_finds_all_files() {
         let tdir = tempdir::TempDir::new("triefort_test").unwrap();
-        let mut hdl = open::<Thing>(tdir.path().to_str().unwrap()).unwrap();
+        let hdl = open::<Thing>(tdir.path().to_str().unwrap()).unwrap();
 
         let t1 = Thing {
             key: vec![1, 2, 3, 4, 5],
@@ -277,4 +858,214 @@ mod tests {
 
         assert_eq!(vec!["0102030405"], found);
     }
+
+    #[test]
+    fn keys_lists_every_item() {
+        let tdir = tempdir::TempDir::new("triefort_test").unwrap();
+        let hdl = open::<Thing>(tdir.path().to_str().unwrap()).unwrap();
+
+        for k in &[
+            [1, 2, 3, 4, 5],
+            [1, 2, 3, 9, 0],
+            [1, 5, 6, 0, 0],
+            [2, 8, 9, 0, 0],
+        ] {
+            assert!(hdl.insert_new(&Thing { key: k.to_vec() }).unwrap());
+        }
+        assert!(!hdl
+            .insert_new(&Thing {
+                key: vec![2, 8, 9, 0, 0]
+            })
+            .unwrap());
+        assert_eq!(4, hdl.all().unwrap().len());
+
+        let mut keys = hdl.keys().unwrap();
+        keys.sort();
+        assert_eq!(
+            vec![
+                vec![1, 2, 3, 4, 5],
+                vec![1, 2, 3, 9, 0],
+                vec![1, 5, 6, 0, 0],
+                vec![2, 8, 9, 0, 0],
+            ],
+            keys
+        );
+    }
+
+    #[test]
+    fn racing_inserts_have_one_winner() {
+        fn shareable<T: Send + Sync>(_: &T) {}
+
+        let tdir = tempdir::TempDir::new("triefort_test").unwrap();
+        let hdl = open::<Thing>(tdir.path().to_str().unwrap()).unwrap();
+        shareable(&hdl);
+
+        let t = Thing {
+            key: vec![9, 9, 9, 9],
+        };
+        let won = ::std::thread::scope(|s| {
+            let racers: Vec<_> = (0..8).map(|_| s.spawn(|| hdl.insert(&t))).collect();
+            racers
+                .into_iter()
+                .map(|r| r.join().unwrap())
+                .filter(|r| r.is_ok())
+                .count()
+        });
+
+        assert_eq!(1, won);
+        assert_eq!(t, hdl.get(&t.key).unwrap());
+        assert_eq!(vec![t.key.clone()], hdl.keys().unwrap());
+        assert_eq!(vec!["09090909"], hdl.find_all_with_prefix(&t.key).unwrap());
+    }
+
+    #[test]
+    fn readers_share_the_lock_and_writers_wait() {
+        let tdir = tempdir::TempDir::new("triefort_test").unwrap();
+        let dir = tdir.path();
+
+        let first = Lock::acquire_shared(dir, || panic!("readers waited")).unwrap();
+        let second = Lock::acquire_shared(dir, || panic!("readers waited")).unwrap();
+        assert!(Lock::file(dir).unwrap().try_lock_exclusive().is_err());
+
+        drop(first);
+        drop(second);
+        let writer = Lock::acquire(dir, || panic!("nobody was reading")).unwrap();
+        assert!(Lock::file(dir).unwrap().try_lock_shared().is_err());
+        drop(writer);
+    }
+
+    #[test]
+    fn the_cache_is_bounded_and_forgets_what_changes() {
+        let tdir = tempdir::TempDir::new("triefort_test").unwrap();
+        let path = tdir.path().to_str().unwrap();
+        let things: Vec<Thing> = (0..3).map(|i| Thing { key: vec![i; 4] }).collect();
+
+        let hdl = open::<Thing>(path)
+            .unwrap()
+            .with_cache(CacheLimit::Entries(2));
+        for t in &things {
+            hdl.insert(t).unwrap();
+        }
+        for t in things.iter().chain(&things[1..]) {
+            assert_eq!(*t, hdl.get(&t.key).unwrap());
+        }
+        let stats = hdl.cache_stats().unwrap();
+        assert_eq!((2, 3, 2), (stats.hits, stats.misses, stats.entries));
+
+        // Each Thing is stored in 30 bytes, so only one fits.
+        let small = open::<Thing>(path)
+            .unwrap()
+            .with_cache(CacheLimit::Bytes(50));
+        small.get(&things[0].key).unwrap();
+        small.get(&things[1].key).unwrap();
+        assert_eq!(1, small.cache_stats().unwrap().entries);
+        assert_eq!(30, small.cache_stats().unwrap().bytes);
+
+        hdl.get(&things[0].key).unwrap();
+        assert_eq!(2, hdl.cache_stats().unwrap().entries);
+        assert!(hdl.remove(&things[2].key).unwrap());
+        assert!(!hdl.remove(&things[2].key).unwrap());
+        assert!(hdl.get(&things[2].key).is_err());
+        assert_eq!(1, hdl.cache_stats().unwrap().entries);
+        assert!(open::<Thing>(path).unwrap().cache_stats().is_none());
+    }
+
+    #[test]
+    fn reads_that_race_a_forget_are_not_cached() {
+        let t = Thing { key: vec![1; 4] };
+        let mut cache = Cache::new(CacheLimit::Entries(2));
+
+        // A read starts, then the item is replaced before it finishes.
+        let seen = cache.generation;
+        cache.forget(&t.key);
+        cache.put(&t.key, t.clone(), 30, seen);
+        assert_eq!(None, cache.get(&t.key));
+
+        cache.put(&t.key, t.clone(), 30, cache.generation);
+        assert_eq!(Some(t.clone()), cache.get(&t.key));
+    }
+
+    #[test]
+    fn compressed_and_uncompressed_files_coexist() {
+        let tdir = tempdir::TempDir::new("triefort_test").unwrap();
+        let path = tdir.path().to_str().unwrap();
+        let mut hdl = open::<Thing>(path).unwrap();
+
+        let legacy = Thing { key: vec![1; 4] };
+        let plain = Thing { key: vec![2; 4] };
+        let squeezed = Thing {
+            key: b"again and again and again and again".to_vec(),
+        };
+
+        // Written before records.
+        fs::create_dir_all(hdl.path(&legacy.key).parent().unwrap()).unwrap();
+        fs::write(hdl.path(&legacy.key), legacy.encode()).unwrap();
+        hdl.insert(&plain).unwrap();
+        hdl.set_compression(Compression::Deflate).unwrap();
+        hdl.insert(&squeezed).unwrap();
+
+        let hdl = open::<Thing>(path).unwrap();
+        assert_eq!(Compression::Deflate, hdl.compression());
+        for t in &[&legacy, &plain, &squeezed] {
+            assert_eq!(**t, hdl.get(&t.key).unwrap());
+        }
+        let stored = hdl.read(&squeezed.key).unwrap();
+        let record = Record::read(&stored).unwrap().unwrap();
+        assert_eq!(DEFLATE, record.compression);
+        assert!(record.payload.len() < squeezed.encode().len());
+
+        assert_eq!(2, hdl.recompress().unwrap());
+        assert_eq!(0, hdl.recompress().unwrap());
+        assert_eq!(legacy, hdl.get(&legacy.key).unwrap());
+        let stored = hdl.read(&legacy.key).unwrap();
+        assert_eq!(DEFLATE, Record::read(&stored).unwrap().unwrap().compression);
+    }
+
+    #[test]
+    fn damage_is_told_apart_from_bad_items() {
+        let tdir = tempdir::TempDir::new("triefort_test").unwrap();
+        let hdl = open::<Thing>(tdir.path().to_str().unwrap()).unwrap();
+        let things: Vec<Thing> = (0..4).map(|i| Thing { key: vec![i; 4] }).collect();
+        for t in &things {
+            hdl.insert(t).unwrap();
+        }
+
+        let mut flipped = hdl.read(&things[0].key).unwrap();
+        *flipped.last_mut().unwrap() ^= 1;
+        fs::write(hdl.path(&things[0].key), flipped).unwrap();
+        match hdl.load(&things[0].key) {
+            Err(LoadError::Checksum { stored, actual }) => assert_ne!(stored, actual),
+            r => panic!("expected a checksum failure, got {:?}", r),
+        }
+        let e = hdl.get(&things[0].key).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, e.kind());
+
+        let cut = hdl.read(&things[1].key).unwrap();
+        fs::write(hdl.path(&things[1].key), &cut[..cut.len() - 1]).unwrap();
+        assert!(matches!(
+            hdl.load(&things[1].key),
+            Err(LoadError::Format(_))
+        ));
+
+        // An intact record whose payload isn't a Thing.
+        let junk = Record::write(Compression::None, &[1]);
+        fs::write(hdl.path(&things[2].key), junk).unwrap();
+        assert!(matches!(hdl.load(&things[2].key), Err(LoadError::Decode)));
+
+        let other = Record::write(Compression::None, &things[0].encode());
+        fs::write(hdl.path(&things[3].key), other).unwrap();
+        assert!(matches!(hdl.load(&things[3].key), Err(LoadError::Check)));
+        assert_eq!(things[0], hdl.get_unchecked(&things[3].key).unwrap());
+
+        // A small file that would inflate past the limit.
+        let big = vec![0; MAX_INFLATED_SIZE + 1];
+        let bomb = Record::wrap(DEFLATE, &Compression::Deflate.compress(&big));
+        assert!(bomb.len() < 10 * 1024);
+        fs::write(hdl.path(&things[3].key), bomb).unwrap();
+        assert!(matches!(hdl.load(&things[3].key), Err(LoadError::Decode)));
+
+        // Items that big are never compressed in the first place.
+        let stored = Record::write(Compression::Deflate, &big);
+        assert_eq!(PLAIN, Record::read(&stored).unwrap().unwrap().compression);
+    }
 }
@@ -9,6 +9,7 @@ use std::collections::HashMap;
 /// In addition, a Universe also contains information about the author
 /// to use for the currently running process.
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct Universe {
     /// The authors we can verify/validate.
     authors: HashMap<author::AuthorId, author::PartialAuthor>,
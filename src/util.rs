@@ -1,3 +1,5 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 
 const DISPLAY_BYTES: usize = 5;
@@ -10,9 +12,69 @@ pub fn debug_bytes(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
     write!(f, ")")
 }
 
+/// Format bytes as a string of lowercase hexadecimal digit pairs.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse a string of hexadecimal digit pairs into bytes. Returns
+/// `None` if the string has an odd length or a non-hex character.
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|p| u8::from_str_radix(p, 16).ok()))
+        .collect()
+}
+
 pub fn display_bytes(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
     for b in bytes.iter().take(DISPLAY_BYTES) {
         write!(f, "{:02x}", b)?;
     }
     Ok(())
 }
+
+/// Serialize `inner` as the hex string of `bytes` in human readable
+/// formats like JSON. Compact formats like bincode get `inner`
+/// unchanged, so stored data keeps its layout.
+pub fn serialize_hex<S, T>(s: S, bytes: &[u8], inner: &T) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    if s.is_human_readable() {
+        s.serialize_str(&to_hex(bytes))
+    } else {
+        inner.serialize(s)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HexOr<T> {
+    Hex(String),
+    Raw(T),
+}
+
+/// The inverse of `serialize_hex`. Human readable formats also accept
+/// the raw form of `T`, which is how older JSON files were written.
+pub fn deserialize_hex<'de, D, T, F>(d: D, from_bytes: F) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+    F: Fn(&[u8]) -> Option<T>,
+{
+    if !d.is_human_readable() {
+        return T::deserialize(d);
+    }
+
+    match HexOr::deserialize(d)? {
+        HexOr::Hex(h) => from_hex(&h)
+            .and_then(|b| from_bytes(&b))
+            .ok_or_else(|| D::Error::custom(format!("bad hex value: {}", h))),
+        HexOr::Raw(t) => Ok(t),
+    }
+}
@@ -0,0 +1,145 @@
+use author::{AuthorId, PartialAuthor};
+use rayon::prelude::*;
+use remnant::{NodeId, Remnant, ValidationErr};
+use sodiumoxide;
+use std::collections::HashMap;
+use std::io;
+use triefort;
+use triefort::LoadError;
+
+/// A Remnant that failed to verify, and why.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub id: NodeId,
+    pub err: ValidationErr,
+}
+
+/// Verify each Remnant against the public key paired with it. The
+/// hashing and signature checks are spread across threads. Every
+/// failure is reported, in the order the pairs were given, so an
+/// empty result means everything verified.
+pub fn verify_all(pairs: &[(&Remnant, &PartialAuthor)]) -> Vec<Failure> {
+    // libsodium picks its implementations on the first call, which
+    // mustn't race.
+    let _ = sodiumoxide::init();
+
+    pairs
+        .par_iter()
+        .filter_map(|(r, a)| {
+            r.verify(a).err().map(|err| Failure {
+                id: r.id().clone(),
+                err,
+            })
+        })
+        .collect()
+}
+
+/// Something wrong with one record in a database.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Problem {
+    pub id: NodeId,
+    pub problem: String,
+}
+
+/// What `check` found.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// How many records were looked at.
+    pub checked: usize,
+
+    /// Records that didn't load or verify, or are missing a parent.
+    pub problems: Vec<Problem>,
+
+    /// Records by authors whose keys aren't among those given, so
+    /// they couldn't be verified.
+    pub unknown: Vec<NodeId>,
+}
+
+/// Check every record in the database: that it loads, that it
+/// verifies against its author's key in `authors`, and that its
+/// parents are present.
+pub fn check(db: &triefort::Handle<Remnant>, authors: &[PartialAuthor]) -> io::Result<Report> {
+    let keys: HashMap<&AuthorId, &PartialAuthor> = authors.iter().map(|a| (a.id(), a)).collect();
+    let mut report = Report::default();
+    let mut remnants = Vec::new();
+
+    for k in db.keys()? {
+        let id = match NodeId::from_bytes(&k) {
+            Some(id) => id,
+            None => continue,
+        };
+        report.checked += 1;
+
+        let problem = match db.load(&k) {
+            Ok(r) => {
+                remnants.push(r);
+                continue;
+            }
+            Err(e @ LoadError::Checksum { .. }) | Err(e @ LoadError::Format(_)) => {
+                format!("damaged on disk: {}", e)
+            }
+            Err(e) => format!("failed to load: {}", e),
+        };
+        report.problems.push(Problem { id, problem });
+    }
+
+    let mut pairs = Vec::with_capacity(remnants.len());
+    for r in &remnants {
+        match keys.get(r.author()) {
+            Some(a) => pairs.push((r, *a)),
+            None => report.unknown.push(r.id().clone()),
+        }
+
+        for p in r.content().parents() {
+            if !db.contains(p.bytes()) {
+                report.problems.push(Problem {
+                    id: r.id().clone(),
+                    problem: format!("missing parent {}", p),
+                });
+            }
+        }
+    }
+
+    for f in verify_all(&pairs) {
+        report.problems.push(Problem {
+            id: f.id,
+            problem: format!("failed to verify: {:?}", f.err),
+        });
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use author::Author;
+
+    #[test]
+    fn every_failure_is_reported() {
+        let a = Author::new();
+        let b = Author::new();
+        let (pa, pb) = (a.partial(), b.partial());
+
+        let mut chain = vec![Remnant::origin(&a, "many")];
+        for i in 0..200 {
+            let next = chain[i].append(&a, format!("{}", i).as_bytes());
+            chain.push(next);
+        }
+
+        let mut pairs: Vec<(&Remnant, &PartialAuthor)> = chain.iter().map(|r| (r, &pa)).collect();
+        assert!(verify_all(&pairs).is_empty());
+
+        pairs[7].1 = &pb;
+        pairs[150].1 = &pb;
+        let failed = verify_all(&pairs);
+        assert_eq!(
+            vec![chain[7].id().clone(), chain[150].id().clone()],
+            failed.iter().map(|f| f.id.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ValidationErr::AuthorMismatch(a.id().clone(), b.id().clone()),
+            failed[0].err
+        );
+    }
+}